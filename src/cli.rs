@@ -1,14 +1,64 @@
+mod atomic;
+mod audit;
 mod builders;
+mod diff;
+mod error;
 mod flags;
+mod lock;
+mod metadata;
+mod name;
+mod otp;
 mod password;
+mod permissions;
 mod repository;
+mod retention;
+mod rotation;
+mod storage;
+mod strength;
+mod trash;
+mod vault;
 mod version;
 
-pub use builders::PasswordBuilder;
-use flags::GetFlags;
-pub use password::Password;
+use audit::AuditReport;
+pub use audit::{
+    Breach, BreachList, ReuseGroup, ReuseKind, ReuseReport, VersionReuse,
+};
+pub use builders::{
+    BuilderError, Capitalization, CharacterClass, CharacterPolicy,
+    PassphraseOptions, PasswordBuilder,
+};
+pub use diff::{FieldChange, NoteLine, PasswordDiff};
+pub use error::RepositoryError;
+use flags::{
+    AuditFlags, DiffFlags, EmptyTrashFlags, GetFlags, InitFlags, ListFlags,
+    MoveFlags, PolicyFlags, PruneFlags, RemoveFlags, RollbackFlags,
+    WriteFlags,
+};
+use metadata::unix_now;
+pub use metadata::VersionMetadata;
+pub use name::PasswordName;
+pub use otp::{Otp, OtpAlgorithm, OtpError, OtpKind};
+pub use password::{CustomField, Password};
 pub use repository::PasswordRepository;
+pub use retention::Retention;
+pub use rotation::RotationPolicy;
+use std::borrow::Cow;
 use std::process::exit;
+use std::time::Duration;
+pub use storage::{
+    DirectoryStorage, MemoryStorage, Storage, StorageFormat, VaultFileStorage,
+};
+pub use strength::{Strength, Weakness};
+pub use trash::TrashEntry;
+pub use vault::{AssociatedData, VaultHeader, VaultKey};
+
+/// Environment variable that, when set, is used as the master password
+/// instead of prompting for it
+const MASTER_PASSWORD_VARIABLE: &str = "PWM_MASTER_PASSWORD";
+
+/// Environment variable with the seconds to wait for other processes using
+/// the password store
+const LOCK_TIMEOUT_VARIABLE: &str = "PWM_LOCK_TIMEOUT";
 
 pub struct CommandLineInterface<I: Iterator<Item = String>> {
     args: I,
@@ -17,6 +67,7 @@ pub struct CommandLineInterface<I: Iterator<Item = String>> {
 }
 
 impl<I: Iterator<Item = String>> CommandLineInterface<I> {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_iter(args: I) -> CommandLineInterface<I> {
         CommandLineInterface {
             args,
@@ -26,25 +77,60 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
     }
 }
 
-impl CommandLineInterface<std::env::Args> {
-    pub fn new() -> CommandLineInterface<std::env::Args> {
+impl Default for CommandLineInterface<std::env::Args> {
+    fn default() -> Self {
         let mut args = std::env::args();
         args.next();
         CommandLineInterface::from_iter(args)
     }
 }
 
+impl CommandLineInterface<std::env::Args> {
+    pub fn new() -> CommandLineInterface<std::env::Args> {
+        CommandLineInterface::default()
+    }
+}
+
 impl<I: Iterator<Item = String>> CommandLineInterface<I> {
     pub fn run(&mut self) {
-        match self.args.next() {
+        let mut subcommand = self.args.next();
+        let insecure_ok = subcommand.as_deref() == Some("--insecure-ok");
+        if insecure_ok {
+            subcommand = self.args.next();
+        }
+        if let Some(timeout) = Self::read_lock_timeout() {
+            self.repository =
+                std::mem::take(&mut self.repository).lock_timeout(timeout);
+        }
+        if !matches!(
+            subcommand.as_deref(),
+            None | Some("init" | "gen" | "help")
+        ) {
+            self.check_permissions(insecure_ok);
+        }
+
+        match subcommand {
             Some(subcommand) => match subcommand.as_str() {
                 "get" => self.get_password(),
                 "new" => self.new_password(),
                 "update" => self.update_password(),
                 "remove" | "rm" => self.remove_password(),
+                "mv" => self.move_password(),
+                "cp" => self.copy_password(),
+                "trash" => self.trash(),
                 "list" => self.list_all_passwords(),
+                "history" => self.show_history(),
+                "diff" => self.diff_versions(),
+                "otp" => self.one_time_password(),
+                "check" => self.check_password(),
+                "audit" => self.audit_passwords(),
+                "policy" => self.rotation_policy(),
+                "stale" => self.stale_passwords(),
+                "rotate" => self.rotate_password(),
+                "rollback" => self.rollback_password(),
+                "prune" => self.prune_versions(),
                 "gen" => self.generate_password(),
-                "init" => Self::passwords_setup(),
+                "init" => self.passwords_setup(),
                 "help" => Self::show_documentation(),
                 _ => {
                     eprintln!("pwm: Unknown subcommand '{subcommand}'\n");
@@ -64,15 +150,39 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
         let password_name = self.password_name_from_args();
         let mut flags = GetFlags::new();
         flags = self.parse_get_flags(flags);
+        self.unlock_repository();
 
-        let password = match self.repository.get(&password_name, flags) {
-            Ok(password) => password,
-            Err(_) => {
-                eprintln!("pwm: Password {password_name} not found");
-                exit(1);
+        let field = flags.field.take();
+        let password =
+            Self::exit_on_error(self.repository.get(&password_name, flags));
+        match field {
+            None => println!("{}", password),
+            Some(field) => println!(
+                "{}",
+                Self::field_or_exit(
+                    &password_name,
+                    password.password(),
+                    &field
+                )
+            ),
+        }
+    }
+
+    /// Value of a field of the password, exiting when it has none
+    fn field_or_exit<'a>(
+        password_name: &PasswordName,
+        password: &'a Password,
+        field: &str,
+    ) -> Cow<'a, str> {
+        match password.field(field) {
+            Some(value) => value,
+            None => {
+                eprintln!(
+                    "pwm: Password {password_name} has no field '{field}'"
+                );
+                exit(2);
             }
-        };
-        println!("{}", password)
+        }
     }
 
     pub fn parse_get_flags(&mut self, mut flags: GetFlags) -> GetFlags {
@@ -91,6 +201,7 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
                             }
                         })
                 }
+                "--field" | "-f" => flags.field = Some(self.flag_value(&arg)),
                 value => {
                     eprintln!("pwm: Unknown flag '{value}' for get command\n")
                 }
@@ -102,65 +213,1094 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
 
     fn new_password(&mut self) {
         let password_name = self.password_name_from_args();
-        match self.args.next() {
-            Some(password_value) => self
-                .repository
-                .add(&Password::new(password_name, password_value)),
-            None => self.create_and_save_password(password_name),
+        let mut flags = self.parse_write_flags(WriteFlags::new(), "new");
+        Self::check_supplied_value(&flags);
+        self.unlock_repository();
+        match flags.value.take() {
+            Some(password_value) => {
+                let password =
+                    Password::new(password_name.to_string(), password_value);
+                self.save_password(&password_name, password, flags, None);
+            }
+            None => self.create_and_save_password(password_name, flags),
         }
     }
 
     fn update_password(&mut self) {
         let password_name = self.password_name_from_args();
+        let mut flags = self.parse_write_flags(WriteFlags::new(), "update");
+        Self::check_supplied_value(&flags);
+        self.unlock_repository();
+        match flags.value.take() {
+            Some(password_value) => {
+                self.save_update(
+                    &password_name,
+                    Some(password_value),
+                    flags,
+                    None,
+                );
+            }
+            // Only generating a value when asked to, or when nothing else
+            // would change, so that editing a field keeps the password
+            None if flags.generate || !flags.sets_fields() => {
+                self.create_and_update_password(&password_name, flags)
+            }
+            None => {
+                self.save_update(&password_name, None, flags, None);
+            }
+        };
+    }
+
+    /// Warns about weak values given on the command line, refusing them
+    /// with `--strict`. Generated values are never checked.
+    fn check_supplied_value(flags: &WriteFlags) {
+        let Some(value) = &flags.value else {
+            return;
+        };
+        let strength = Strength::estimate(value);
+        if !strength.is_weak() {
+            return;
+        }
+
+        let weaknesses: Vec<String> = strength
+            .weaknesses()
+            .iter()
+            .map(|weakness| weakness.to_string())
+            .collect();
+        eprintln!(
+            "pwm: Warning: weak password, score {}/4: {}",
+            strength.score(),
+            weaknesses.join(", ")
+        );
+        if flags.strict {
+            eprintln!("pwm: Refusing weak password because of --strict");
+            exit(1)
+        }
+    }
+
+    fn parse_write_flags(
+        &mut self,
+        mut flags: WriteFlags,
+        command: &str,
+    ) -> WriteFlags {
         match self.args.next() {
-            Some(password_value) => self
-                .repository
-                .update(&Password::new(password_name, password_value)),
-            None => self.create_and_update_password(password_name),
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--message" | "-m" => {
+                    flags.message = Some(self.flag_value(&arg))
+                }
+                "--username" | "-u" => {
+                    flags.username = Some(self.flag_value(&arg))
+                }
+                "--url" => flags.urls.push(self.flag_value(&arg)),
+                "--notes" => flags.notes = Some(self.flag_value(&arg)),
+                "--strict" => flags.strict = true,
+                "--otp" => {
+                    let otp = self.flag_value(&arg);
+                    if let Err(error) = Otp::parse(&otp) {
+                        eprintln!("pwm: {error}");
+                        exit(1)
+                    }
+                    flags.otp = Some(otp);
+                }
+                "--field" | "--secret-field" => {
+                    let field = self.flag_value(&arg);
+                    let Some((name, value)) = field.split_once('=') else {
+                        eprintln!(
+                            "pwm: Fields must be given as NAME=VALUE, got '{field}'"
+                        );
+                        exit(1)
+                    };
+                    flags.fields.push(CustomField::new(
+                        name.to_string(),
+                        value.to_string(),
+                        arg == "--secret-field",
+                    ));
+                }
+                flag if self.parse_generator_flag(flag) => {
+                    flags.generate = true
+                }
+                value if flags.value.is_none() => {
+                    flags.value = Some(value.to_string())
+                }
+                value => {
+                    eprintln!(
+                        "pwm: Unexpected argument '{value}' for {command} command"
+                    );
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_write_flags(flags, command)
+    }
+
+    /// Applies a flag configuring how passwords are generated to the
+    /// builder, returning whether the flag was one of them
+    fn parse_generator_flag(&mut self, flag: &str) -> bool {
+        let mut passphrase = self
+            .builder
+            .passphrase_options()
+            .cloned()
+            .unwrap_or_default();
+        match flag {
+            "--passphrase" => (),
+            "--words" => {
+                let words = self.flag_value(flag);
+                match words.parse::<usize>() {
+                    Ok(words)
+                        if (1..=builders::MAX_WORDS).contains(&words) =>
+                    {
+                        passphrase = passphrase.words(words)
+                    }
+                    _ => {
+                        eprintln!(
+                            "pwm: Incorrect number of words '{words}', expected 1 to {}",
+                            builders::MAX_WORDS
+                        );
+                        exit(1)
+                    }
+                }
+            }
+            "--separator" => {
+                passphrase = passphrase.separator(self.flag_value(flag))
+            }
+            "--capitalize" => {
+                match self.flag_value(flag).parse::<Capitalization>() {
+                    Ok(capitalization) => {
+                        passphrase = passphrase.capitalization(capitalization)
+                    }
+                    Err(error) => {
+                        eprintln!("pwm: {error}");
+                        exit(1)
+                    }
+                }
+            }
+            "--digit" => passphrase = passphrase.digit(true),
+            "--symbol" => passphrase = passphrase.symbol(true),
+            _ => {
+                return self.parse_length_flag(flag)
+                    || self.parse_policy_flag(flag)
+            }
+        }
+
+        self.builder = self.builder.clone().passphrase(passphrase);
+        true
+    }
+
+    /// Applies a flag restricting which characters generated passwords may
+    /// contain, returning whether the flag was one of them
+    fn parse_policy_flag(&mut self, flag: &str) -> bool {
+        let policy = self.builder.policy().clone();
+        let class = |name: &str| name.parse::<CharacterClass>().ok();
+
+        let policy = if let Some(class) =
+            flag.strip_prefix("--no-").and_then(class)
+        {
+            policy.enable(class, false)
+        } else if let Some(class) = flag.strip_prefix("--min-").and_then(class)
+        {
+            let minimum = self.flag_value(flag);
+            match minimum.parse::<u16>() {
+                Ok(minimum) => policy.minimum(class, minimum),
+                Err(_) => {
+                    eprintln!(
+                        "pwm: Incorrect minimum '{minimum}' for {class}"
+                    );
+                    exit(1)
+                }
+            }
+        } else {
+            match flag {
+                "--symbols" => policy.symbols(&self.flag_value(flag)),
+                "--exclude" => policy.exclude(&self.flag_value(flag)),
+                "--avoid-ambiguous" => policy.avoid_ambiguous(true),
+                _ => return false,
+            }
         };
+
+        self.builder = self.builder.clone().character_policy(policy);
+        true
     }
 
-    fn create_and_save_password(&self, password_name: String) {
-        let password = self.builder.build(password_name);
-        self.repository.add(&password);
-        println!("{}", password)
+    /// Applies a flag setting the length of generated passwords,
+    /// returning whether the flag was one of them
+    fn parse_length_flag(&mut self, flag: &str) -> bool {
+        if !matches!(flag, "--length" | "--min" | "--max") {
+            return false;
+        }
+        let length = self.flag_value(flag);
+        let length = match length.parse::<u16>() {
+            Ok(length) => length,
+            Err(_) => {
+                eprintln!("pwm: Incorrect length '{length}' for '{flag}'");
+                exit(1)
+            }
+        };
+
+        let builder = self.builder.clone();
+        self.builder = match flag {
+            "--length" => builder.length(length),
+            "--min" => builder.min_length(length),
+            _ => builder.max_length(length),
+        };
+        true
     }
 
-    fn create_and_update_password(&self, password_name: String) {
-        let password = self.builder.build(password_name);
-        self.repository.update(&password);
-        println!("{}", password)
+    fn generate_value(&self) -> String {
+        match self.builder.build_secure_password() {
+            Ok(value) => value,
+            Err(error) => {
+                eprintln!("pwm: Couldn't generate password: {error}");
+                exit(1)
+            }
+        }
+    }
+
+    fn parse_gen_flags(&mut self) {
+        match self.args.next() {
+            None => return,
+            Some(arg) => {
+                if !self.parse_generator_flag(&arg) {
+                    eprintln!("pwm: Unknown flag '{arg}' for gen command");
+                    exit(1)
+                }
+            }
+        }
+
+        self.parse_gen_flags()
+    }
+
+    /// Reports the strength of generated passphrases on stderr, so that
+    /// stdout only holds the generated value
+    fn report_entropy(&self) {
+        if let Some(passphrase) = self.builder.passphrase_options() {
+            eprintln!("Entropy: {:.1} bits", passphrase.entropy_bits());
+        }
+    }
+
+    fn flag_value(&mut self, flag: &str) -> String {
+        match self.args.next() {
+            Some(value) => value,
+            None => {
+                eprintln!("pwm: No value provided for flag '{flag}'");
+                exit(1)
+            }
+        }
+    }
+
+    /// Saves a new password. `generator` holds the builder settings when
+    /// the value was generated, so that `rotate` can reuse them.
+    fn save_password(
+        &self,
+        password_name: &PasswordName,
+        password: Password,
+        flags: WriteFlags,
+        generator: Option<PasswordBuilder>,
+    ) -> Password {
+        let metadata = VersionMetadata::new()
+            .with_message(flags.message.clone())
+            .with_generator(generator);
+        let password = flags.apply(password);
+        Self::exit_on_error(self.repository.add(
+            password_name,
+            &password,
+            metadata,
+        ));
+        password
+    }
+
+    /// Updates the latest version of a password with `value`, or keeps
+    /// its value when there is none, returning the previous and the new
+    /// content
+    fn save_update(
+        &self,
+        password_name: &PasswordName,
+        value: Option<String>,
+        flags: WriteFlags,
+        generator: Option<PasswordBuilder>,
+    ) -> (Password, Password) {
+        let metadata = VersionMetadata::new()
+            .with_message(flags.message.clone())
+            .with_generator(generator);
+        let mut previous = None;
+        let updated = Self::exit_on_error(self.repository.modify(
+            password_name,
+            |latest| {
+                previous = Some(latest.clone());
+                let password = match value {
+                    Some(value) => latest.clone().with_value(value),
+                    None => latest.clone(),
+                };
+                Ok((flags.apply(password), metadata))
+            },
+        ));
+        let previous = previous.expect("modify reads the latest version");
+        (previous, updated.password().clone())
+    }
+
+    fn create_and_save_password(
+        &self,
+        password_name: PasswordName,
+        flags: WriteFlags,
+    ) {
+        let password =
+            Password::new(password_name.to_string(), self.generate_value());
+        let password = self.save_password(
+            &password_name,
+            password,
+            flags,
+            Some(self.builder.clone()),
+        );
+        println!("{}", password);
+        self.report_entropy();
+    }
+
+    fn create_and_update_password(
+        &self,
+        password_name: &PasswordName,
+        flags: WriteFlags,
+    ) {
+        let (_, password) = self.save_update(
+            password_name,
+            Some(self.generate_value()),
+            flags,
+            Some(self.builder.clone()),
+        );
+        println!("{}", password);
+        self.report_entropy();
     }
 
     fn remove_password(&mut self) {
         let password_name = self.password_name_from_args();
+        let flags = self.parse_remove_flags(RemoveFlags::new());
+        self.open_repository();
 
-        println!(
-            "Are you sure you want to delete the password? (yes/no) [no]",
-        );
+        if flags.permanent {
+            println!(
+                "Are you sure you want to permanently delete the password? (yes/no) [no]",
+            );
+        } else {
+            println!(
+                "Are you sure you want to delete the password? (yes/no) [no]",
+            );
+        }
         let mut user_confirmation = String::new();
         match std::io::stdin().read_line(&mut user_confirmation) {
             Ok(_) => (),
             Err(_) => user_confirmation = String::from("no"),
         };
 
-        if user_confirmation.trim().to_lowercase() == "yes" {
-            self.repository.remove(&password_name);
-        } else {
+        if user_confirmation.trim().to_lowercase() != "yes" {
             println!("Password deletion aborted");
+        } else if flags.permanent {
+            Self::exit_on_error(
+                self.repository.remove_permanently(&password_name),
+            );
+        } else {
+            Self::exit_on_error(self.repository.remove(&password_name));
+            eprintln!(
+                "Moved {password_name} to the trash, restore it with `pwm trash restore {password_name}`"
+            );
+        }
+    }
+
+    fn parse_remove_flags(&mut self, mut flags: RemoveFlags) -> RemoveFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--permanent" => flags.permanent = true,
+                value => {
+                    eprintln!("pwm: Unknown flag '{value}' for rm command");
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_remove_flags(flags)
+    }
+
+    fn trash(&mut self) {
+        self.open_repository();
+        match self.args.next().as_deref() {
+            None | Some("list") => {
+                let trash = Self::exit_on_error(self.repository.trash());
+                if trash.is_empty() {
+                    eprintln!("Trash is empty");
+                }
+                for entry in trash {
+                    println!("{entry}");
+                }
+            }
+            Some("restore") => {
+                let password_name = self.password_name_from_args();
+                Self::exit_on_error(self.repository.restore(&password_name));
+            }
+            Some("empty") => {
+                let flags =
+                    self.parse_empty_trash_flags(EmptyTrashFlags::new());
+                let emptied = Self::exit_on_error(
+                    self.repository.empty_trash(flags.older_than),
+                );
+                eprintln!(
+                    "Deleted {} passwords from the trash",
+                    emptied.len()
+                );
+            }
+            Some(action) => {
+                eprintln!(
+                    "pwm: Unknown trash action '{action}', expected list, restore or empty"
+                );
+                exit(1)
+            }
+        }
+    }
+
+    fn parse_empty_trash_flags(
+        &mut self,
+        mut flags: EmptyTrashFlags,
+    ) -> EmptyTrashFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--older-than" => {
+                    let age = self.flag_value(&arg);
+                    match metadata::parse_age(&age) {
+                        Some(age) => flags.older_than = Some(age),
+                        None => {
+                            eprintln!(
+                                "pwm: Invalid age '{age}', expected a number of days such as 30d"
+                            );
+                            exit(1)
+                        }
+                    }
+                }
+                value => {
+                    eprintln!(
+                        "pwm: Unknown flag '{value}' for trash empty command"
+                    );
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_empty_trash_flags(flags)
+    }
+
+    fn move_password(&mut self) {
+        let source = self.password_name_from_args();
+        let destination = self.password_name_from_args();
+        let flags = self.parse_move_flags(MoveFlags::new(), "mv");
+        self.unlock_repository();
+        Self::exit_on_error(self.repository.rename(
+            &source,
+            &destination,
+            flags.force,
+        ));
+    }
+
+    fn copy_password(&mut self) {
+        let source = self.password_name_from_args();
+        let destination = self.password_name_from_args();
+        let flags = self.parse_move_flags(MoveFlags::new(), "cp");
+        self.unlock_repository();
+        Self::exit_on_error(self.repository.copy(
+            &source,
+            &destination,
+            flags.force,
+        ));
+    }
+
+    fn parse_move_flags(
+        &mut self,
+        mut flags: MoveFlags,
+        command: &str,
+    ) -> MoveFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--force" => flags.force = true,
+                value => {
+                    eprintln!(
+                        "pwm: Unknown flag '{value}' for {command} command"
+                    );
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_move_flags(flags, command)
+    }
+
+    fn list_all_passwords(&mut self) {
+        let flags = self.parse_list_flags(ListFlags::new());
+        self.open_repository();
+        let names = match &flags.prefix {
+            Some(prefix) => {
+                let prefix = Self::exit_on_error(PasswordName::new(
+                    prefix.trim_end_matches(name::SEPARATOR),
+                ));
+                self.repository.list_under(&prefix)
+            }
+            None => self.repository.list(),
+        };
+        let names = Self::exit_on_error(names);
+
+        if flags.flat {
+            for password_name in names {
+                println!("{}", password_name);
+            }
+        } else if !names.is_empty() {
+            println!("{}", name::tree(&names));
+        }
+    }
+
+    fn parse_list_flags(&mut self, mut flags: ListFlags) -> ListFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--flat" => flags.flat = true,
+                prefix
+                    if flags.prefix.is_none() && !prefix.starts_with('-') =>
+                {
+                    flags.prefix = Some(prefix.to_string())
+                }
+                value => {
+                    eprintln!("pwm: Unknown flag '{value}' for list command");
+                    exit(1)
+                }
+            },
         }
+
+        self.parse_list_flags(flags)
     }
 
-    fn list_all_passwords(&self) {
-        self.repository.list();
+    fn show_history(&mut self) {
+        let password_name = self.password_name_from_args();
+        self.unlock_repository();
+
+        for (version, metadata) in
+            Self::exit_on_error(self.repository.history(&password_name))
+        {
+            match metadata {
+                Some(metadata) => println!("{version:>4}  {metadata}"),
+                None => println!("{version:>4}  (no metadata)"),
+            }
+        }
     }
 
-    fn generate_password(&self) {
-        let password = self.builder.build_secure_password();
+    /// Shows what changed between two versions, by default the latest one
+    /// and the one before it
+    fn diff_versions(&mut self) {
+        let password_name = self.password_name_from_args();
+        let flags = self.parse_diff_flags(DiffFlags::new());
+        self.unlock_repository();
+
+        let versions =
+            Self::exit_on_error(self.repository.versions(&password_name));
+        let (old, new) = match flags.versions[..] {
+            [] => match versions[..] {
+                [.., old, new] => (old, new),
+                _ => {
+                    eprintln!(
+                        "pwm: Password {password_name} has a single version"
+                    );
+                    exit(1)
+                }
+            },
+            [old] => (old, versions.last().copied().unwrap_or_default()),
+            [old, new] => (old, new),
+            _ => {
+                eprintln!("pwm: diff compares two versions at most");
+                exit(1)
+            }
+        };
+
+        let get = |version| {
+            let flags = GetFlags {
+                version: Some(version),
+                ..GetFlags::new()
+            };
+            Self::exit_on_error(self.repository.get(&password_name, flags))
+        };
+        let diff =
+            PasswordDiff::new(&get(old), &get(new)).reveal(flags.reveal);
+        if diff.is_empty() {
+            eprintln!("Versions {old} and {new} are identical");
+        } else {
+            println!("{diff}");
+        }
+    }
+
+    fn parse_diff_flags(&mut self, mut flags: DiffFlags) -> DiffFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--reveal" => flags.reveal = true,
+                value => match value.parse::<u32>() {
+                    Ok(version) => flags.versions.push(version),
+                    Err(_) => {
+                        eprintln!(
+                            "pwm: Unexpected argument '{value}' for diff command"
+                        );
+                        exit(1)
+                    }
+                },
+            },
+        }
+
+        self.parse_diff_flags(flags)
+    }
+
+    fn check_password(&mut self) {
+        let password_name = self.password_name_from_args();
+        let mut flags = GetFlags::new();
+        flags = self.parse_get_flags(flags);
+        self.unlock_repository();
+
+        let field = flags.field.take();
+        let password =
+            Self::exit_on_error(self.repository.get(&password_name, flags));
+        let value = match field {
+            None => Cow::Borrowed(password.password().value()),
+            Some(field) => Self::field_or_exit(
+                &password_name,
+                password.password(),
+                &field,
+            ),
+        };
+        println!("{}", Strength::estimate(&value));
+    }
+
+    fn audit_passwords(&mut self) {
+        let flags = self.parse_audit_flags(AuditFlags::new());
+        if flags.breaches.is_none() && !flags.reuse {
+            eprintln!(
+                "pwm: No audit selected, use --breaches <FILE> or --reuse"
+            );
+            exit(1)
+        }
+        let breach_list =
+            flags
+                .breaches
+                .as_ref()
+                .map(|path| match BreachList::open(path) {
+                    Ok(breach_list) => breach_list,
+                    Err(error) => {
+                        eprintln!(
+                            "pwm: Couldn't open breach list {}: {error}",
+                            path.display()
+                        );
+                        exit(9)
+                    }
+                });
+        self.unlock_repository();
+
+        let report = AuditReport {
+            breaches: breach_list.map(|breach_list| {
+                Self::exit_on_error(audit::find_breaches(
+                    &self.repository,
+                    &breach_list,
+                    flags.all_versions,
+                ))
+            }),
+            reuse: flags.reuse.then(|| {
+                Self::exit_on_error(audit::find_reuse(&self.repository))
+            }),
+        };
+        if flags.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report)
+                    .expect("Audit reports can always be serialized")
+            );
+            return;
+        }
+
+        if let Some(breaches) = report.breaches {
+            for breach in &breaches {
+                println!(
+                    "{} (version {}): seen {} times in breaches",
+                    breach.name, breach.version, breach.occurrences
+                );
+            }
+            eprintln!("{} compromised passwords found", breaches.len());
+        }
+        if let Some(reuse) = report.reuse {
+            if reuse.is_empty() {
+                eprintln!("No reused passwords found");
+            } else {
+                println!("{reuse}");
+            }
+        }
+    }
+
+    fn parse_audit_flags(&mut self, mut flags: AuditFlags) -> AuditFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--breaches" => {
+                    flags.breaches = Some(self.flag_value(&arg).into())
+                }
+                "--all-versions" => flags.all_versions = true,
+                "--reuse" => flags.reuse = true,
+                "--json" => flags.json = true,
+                value => {
+                    eprintln!("pwm: Unknown flag '{value}' for audit command");
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_audit_flags(flags)
+    }
+
+    fn rotation_policy(&mut self) {
+        let flags = self.parse_policy_flags(PolicyFlags::new());
+        self.unlock_repository();
+
+        let policy = Self::exit_on_error(self.repository.rotation_policy());
+        if flags.max_age.is_none() && !flags.clear {
+            if policy.is_empty() {
+                eprintln!("No rotation policy configured");
+            } else {
+                println!("{policy}");
+            }
+            return;
+        }
+        let policy = policy.max_age(flags.prefix, flags.max_age);
+        Self::exit_on_error(self.repository.set_rotation_policy(&policy));
+    }
+
+    fn parse_policy_flags(&mut self, mut flags: PolicyFlags) -> PolicyFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--max-age" => {
+                    let days = self.flag_value(&arg);
+                    match days.parse::<u32>() {
+                        Ok(days) if days > 0 => flags.max_age = Some(days),
+                        _ => {
+                            eprintln!(
+                                "pwm: Incorrect number of days '{days}'"
+                            );
+                            exit(1)
+                        }
+                    }
+                }
+                "--clear" => flags.clear = true,
+                prefix
+                    if flags.prefix.is_none() && !prefix.starts_with('-') =>
+                {
+                    flags.prefix = Some(prefix.to_string())
+                }
+                value => {
+                    eprintln!(
+                        "pwm: Unknown flag '{value}' for policy command"
+                    );
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_policy_flags(flags)
+    }
+
+    fn stale_passwords(&mut self) {
+        self.unlock_repository();
+        let policy = Self::exit_on_error(self.repository.rotation_policy());
+        if policy.is_empty() {
+            eprintln!(
+                "No rotation policy configured, set one with pwm policy --max-age <DAYS>"
+            );
+            return;
+        }
+
+        let now = unix_now();
+        for name in Self::exit_on_error(self.repository.list()) {
+            let Some(max_age) = policy.max_age_of(name.as_str()) else {
+                continue;
+            };
+            let history = Self::exit_on_error(self.repository.history(&name));
+            match history.first() {
+                Some((_, Some(metadata))) => {
+                    if policy.is_stale(
+                        name.as_str(),
+                        metadata.created_at(),
+                        now,
+                    ) {
+                        println!(
+                            "{name}: {} days old, rotate every {max_age} days",
+                            rotation::age_in_days(metadata.created_at(), now)
+                        );
+                    }
+                }
+                _ => println!(
+                    "{name}: unknown age, rotate every {max_age} days"
+                ),
+            }
+        }
+    }
+
+    /// Replaces the value of a password with one generated with the
+    /// builder settings of its latest generated version, so that a
+    /// password that must be a 6 digit PIN stays one
+    fn rotate_password(&mut self) {
+        let password_name = self.password_name_from_args();
+        self.unlock_repository();
+
+        let history =
+            Self::exit_on_error(self.repository.history(&password_name));
+        if let Some(generator) = history
+            .iter()
+            .find_map(|(_, metadata)| metadata.as_ref()?.generator())
+        {
+            self.builder = generator.clone();
+        }
+
+        // Flags are applied on top of the saved settings
+        let mut flags = self.parse_write_flags(WriteFlags::new(), "rotate");
+        if flags.value.is_some() {
+            eprintln!("pwm: rotate generates the value, use update to set it");
+            exit(1)
+        }
+        if flags.message.is_none() {
+            flags.message = Some("Rotated".to_string());
+        }
+
+        let value = self.generate_value();
+        let (previous, _) = self.save_update(
+            &password_name,
+            Some(value.clone()),
+            flags,
+            Some(self.builder.clone()),
+        );
+        println!("Old: {}", previous.value());
+        println!("New: {value}");
+        self.report_entropy();
+    }
+
+    /// Makes an old version current again by writing its content as a new
+    /// version
+    fn rollback_password(&mut self) {
+        let password_name = self.password_name_from_args();
+        let version = match self.args.next() {
+            Some(version) => match version.parse::<u32>() {
+                Ok(version) => version,
+                Err(_) => {
+                    eprintln!("pwm: Incorrect version number '{version}'");
+                    exit(1)
+                }
+            },
+            None => {
+                eprintln!("pwm: No version provided to roll back to");
+                exit(1)
+            }
+        };
+        let flags = self.parse_rollback_flags(RollbackFlags::new());
+        self.unlock_repository();
+
+        let rolled_back = Self::exit_on_error(self.repository.rollback(
+            &password_name,
+            version,
+            flags.message,
+        ));
+        eprintln!(
+            "Rolled back {password_name} to version {version} as version {}",
+            rolled_back.version()
+        );
+    }
+
+    fn parse_rollback_flags(
+        &mut self,
+        mut flags: RollbackFlags,
+    ) -> RollbackFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--message" | "-m" => {
+                    flags.message = Some(self.flag_value(&arg))
+                }
+                value => {
+                    eprintln!(
+                        "pwm: Unknown flag '{value}' for rollback command"
+                    );
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_rollback_flags(flags)
+    }
+
+    /// Deletes old versions of one password, or of every password, that
+    /// the retention flags don't keep
+    fn prune_versions(&mut self) {
+        let flags = self.parse_prune_flags(PruneFlags::new());
+        let mut retention = Retention::new();
+        if let Some(count) = flags.keep_last {
+            retention = retention.keep_last(count);
+        }
+        if let Some(age) = flags.keep_newer_than {
+            retention = retention.keep_newer_than(age);
+        }
+        if retention.is_empty() {
+            eprintln!(
+                "pwm: Choose what to keep with --keep-last <N> or --keep-newer-than <AGE>"
+            );
+            exit(1)
+        }
+        self.unlock_repository();
+        let names = match flags.name {
+            Some(name) => vec![Self::exit_on_error(PasswordName::new(name))],
+            None => Self::exit_on_error(self.repository.list()),
+        };
+
+        let mut total = 0;
+        for name in names {
+            let pruned = Self::exit_on_error(self.repository.prune(
+                &name,
+                &retention,
+                flags.dry_run,
+            ));
+            if pruned.is_empty() {
+                continue;
+            }
+            let versions: Vec<String> =
+                pruned.iter().map(|version| version.to_string()).collect();
+            println!("{name}: {}", versions.join(", "));
+            total += pruned.len();
+        }
+        if flags.dry_run {
+            eprintln!("Would prune {total} versions");
+        } else {
+            eprintln!("Pruned {total} versions");
+        }
+    }
+
+    fn parse_prune_flags(&mut self, mut flags: PruneFlags) -> PruneFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--keep-last" => {
+                    let count = self.flag_value(&arg);
+                    match count.parse::<u32>() {
+                        Ok(count) => flags.keep_last = Some(count),
+                        Err(_) => {
+                            eprintln!(
+                                "pwm: Invalid number of versions '{count}'"
+                            );
+                            exit(1)
+                        }
+                    }
+                }
+                "--keep-newer-than" => {
+                    let age = self.flag_value(&arg);
+                    match metadata::parse_age(&age) {
+                        Some(age) => flags.keep_newer_than = Some(age),
+                        None => {
+                            eprintln!(
+                                "pwm: Invalid age '{age}', expected a number of days such as 30d"
+                            );
+                            exit(1)
+                        }
+                    }
+                }
+                "--dry-run" => flags.dry_run = true,
+                name if flags.name.is_none() && !name.starts_with('-') => {
+                    flags.name = Some(name.to_string())
+                }
+                value => {
+                    eprintln!("pwm: Unknown flag '{value}' for prune command");
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_prune_flags(flags)
+    }
+
+    fn one_time_password(&mut self) {
+        let password_name = self.password_name_from_args();
+        self.unlock_repository();
+
+        let password = Self::exit_on_error(
+            self.repository.get(&password_name, GetFlags::new()),
+        );
+        let password = password.password();
+        let Some(source) = password.otp() else {
+            eprintln!("pwm: Password {password_name} has no field 'otp'");
+            exit(2);
+        };
+        let otp = match Otp::parse(source) {
+            Ok(otp) => otp,
+            Err(error) => Self::exit_with_error(RepositoryError::Corrupt(
+                format!("{password_name}: {error}"),
+            )),
+        };
+
+        match otp.kind() {
+            OtpKind::Totp { .. } => {
+                let (code, seconds_left) = otp.code();
+                println!("{code} ({seconds_left}s left)");
+            }
+            // The counter is read again under the lock it is advanced with,
+            // so that concurrent runs never hand out the same code
+            OtpKind::Hotp { .. } => {
+                let mut code = String::new();
+                Self::exit_on_error(self.repository.modify(
+                    &password_name,
+                    |latest| {
+                        let (next, current) =
+                            Self::advance_hotp(&password_name, latest)?;
+                        code = current;
+                        Ok(next)
+                    },
+                ));
+                println!("{code}");
+            }
+        }
+    }
+
+    /// Next version of a password with its HOTP counter advanced, and the
+    /// code of the counter it had
+    fn advance_hotp(
+        password_name: &PasswordName,
+        latest: &Password,
+    ) -> Result<((Password, VersionMetadata), String), RepositoryError> {
+        let source = latest.otp().unwrap_or_default();
+        let otp = Otp::parse(source).map_err(|error| {
+            RepositoryError::Corrupt(format!("{password_name}: {error}"))
+        })?;
+        let OtpKind::Hotp { counter } = otp.kind() else {
+            return Err(RepositoryError::Corrupt(format!(
+                "{password_name}: HOTP generator changed while advancing it"
+            )));
+        };
+
+        let next_counter = otp::next_counter(counter).map_err(|error| {
+            RepositoryError::Corrupt(format!("{password_name}: {error}"))
+        })?;
+
+        let next = latest
+            .clone()
+            .with_otp(otp::with_counter(source, next_counter));
+        let metadata = VersionMetadata::new().with_message(Some(format!(
+            "HOTP counter advanced to {next_counter}"
+        )));
+        Ok(((next, metadata), otp.code_at_counter(counter)))
+    }
+
+    fn generate_password(&mut self) {
+        self.parse_gen_flags();
+        let password = self.generate_value();
         println!("{}", password);
+        self.report_entropy();
     }
 
-    fn password_name_from_args(&mut self) -> String {
+    /// Password name given as the next argument, exiting before anything
+    /// is read or written if it is missing or invalid
+    fn password_name_from_args(&mut self) -> PasswordName {
         let password_name = match self.args.next() {
             Some(password_name) => password_name,
             None => {
@@ -169,17 +1309,199 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
             }
         };
 
-        password_name
+        Self::exit_on_error(PasswordName::new(password_name))
+    }
+
+    fn passwords_setup(&mut self) {
+        let flags = self.parse_init_flags(InitFlags::new());
+        if self.repository.is_initialized() {
+            Self::exit_with_error(RepositoryError::AlreadyInitialized);
+        }
+
+        let master_password = Self::read_new_master_password();
+        self.repository =
+            std::mem::take(&mut self.repository).storage_format(flags.format);
+        Self::exit_on_error(self.repository.init(&master_password));
+    }
+
+    fn parse_init_flags(&mut self, mut flags: InitFlags) -> InitFlags {
+        match self.args.next() {
+            None => return flags,
+            Some(arg) => match arg.as_str() {
+                "--format" => {
+                    let format = self.flag_value(&arg);
+                    match format.parse() {
+                        Ok(format) => flags.format = format,
+                        Err(error) => {
+                            eprintln!("pwm: {error}");
+                            exit(1)
+                        }
+                    }
+                }
+                value => {
+                    eprintln!("pwm: Unknown flag '{value}' for init command");
+                    exit(1)
+                }
+            },
+        }
+
+        self.parse_init_flags(flags)
+    }
+
+    /// Refuses to touch a store that other users can access, or only warns
+    /// about it when `--insecure-ok` was given
+    fn check_permissions(&mut self, insecure_ok: bool) {
+        if !self.repository.is_initialized() {
+            return;
+        }
+
+        match self.repository.check_permissions() {
+            Ok(()) => {}
+            Err(error @ RepositoryError::Insecure(_)) if insecure_ok => {
+                eprintln!("pwm: Warning: {error}");
+                self.repository =
+                    std::mem::take(&mut self.repository).allow_insecure(true);
+            }
+            Err(error) => Self::exit_with_error(error),
+        }
+    }
+
+    /// Unlocks the repository only if the names of its passwords are
+    /// encrypted, for commands that don't read any value
+    fn open_repository(&mut self) {
+        if self.repository.encrypts_names() {
+            self.unlock_repository();
+        } else {
+            Self::exit_on_error(self.repository.recover());
+        }
+    }
+
+    fn unlock_repository(&mut self) {
+        if !self.repository.is_initialized() {
+            Self::exit_with_error(RepositoryError::NotInitialized);
+        }
+        if self.repository.is_unlocked() {
+            return;
+        }
+
+        let master_password = Self::read_master_password("Master password: ");
+        Self::exit_on_error(self.repository.unlock(&master_password));
+    }
+
+    fn exit_on_error<T>(result: Result<T, RepositoryError>) -> T {
+        match result {
+            Ok(value) => value,
+            Err(error) => Self::exit_with_error(error),
+        }
+    }
+
+    /// Prints a user facing message for the error and exits with the code
+    /// documented for it in the README
+    fn exit_with_error(error: RepositoryError) -> ! {
+        let exit_code = match &error {
+            RepositoryError::NotFound(name) => {
+                eprintln!("pwm: Password {name} not found");
+                2
+            }
+            RepositoryError::VersionNotFound { name, version } => {
+                eprintln!("pwm: Password {name} has no version {version}");
+                3
+            }
+            RepositoryError::AlreadyExists(_) => {
+                eprintln!("pwm: Password already exists. To update an existing password run:\n\n  `pwm update <PASSWORD_NAME> [PASSWORD VALUE]`");
+                4
+            }
+            RepositoryError::InvalidName { .. } => {
+                eprintln!("pwm: {error}");
+                1
+            }
+            RepositoryError::NotInitialized => {
+                eprintln!("pwm: Password manager is not initialized. Try running\n\n  `pwm init`");
+                5
+            }
+            RepositoryError::AlreadyInitialized => {
+                eprintln!("pwm: Password manager is already initialized");
+                6
+            }
+            RepositoryError::Unencrypted(_) => {
+                eprintln!("pwm: Refusing to initialize, the {error} from before encryption. Move them out of the store, run `pwm init` and add them again with `pwm new`");
+                6
+            }
+            RepositoryError::Locked | RepositoryError::WrongMasterPassword => {
+                eprintln!("pwm: Couldn't unlock passwords: {error}");
+                7
+            }
+            RepositoryError::Insecure(_) => {
+                eprintln!("pwm: Refusing to open the {error}. Make it private with `chmod -R go-rwx` or pass --insecure-ok to use it anyway");
+                10
+            }
+            RepositoryError::Busy(_) => {
+                eprintln!("pwm: The {error}. Another pwm is still using it, try again later or raise {LOCK_TIMEOUT_VARIABLE}");
+                11
+            }
+            RepositoryError::Corrupt(_) => {
+                eprintln!("pwm: {error}");
+                8
+            }
+            RepositoryError::Io(_) => {
+                eprintln!("pwm: {error}");
+                9
+            }
+        };
+        exit(exit_code);
+    }
+
+    fn read_lock_timeout() -> Option<Duration> {
+        let timeout = std::env::var(LOCK_TIMEOUT_VARIABLE).ok()?;
+        match timeout.parse::<f64>().map(Duration::try_from_secs_f64) {
+            Ok(Ok(timeout)) => Some(timeout),
+            _ => {
+                eprintln!("pwm: Invalid {LOCK_TIMEOUT_VARIABLE} '{timeout}', expected a number of seconds");
+                exit(1);
+            }
+        }
     }
 
-    fn passwords_setup() {
-        Password::create_home_directory();
+    fn read_master_password(prompt: &str) -> String {
+        if let Ok(master_password) = std::env::var(MASTER_PASSWORD_VARIABLE) {
+            return master_password;
+        }
+
+        match rpassword::prompt_password(prompt) {
+            Ok(master_password) => master_password,
+            Err(_) => {
+                eprintln!("pwm: Couldn't read master password");
+                exit(1);
+            }
+        }
+    }
+
+    fn read_new_master_password() -> String {
+        let (master_password, prompted) =
+            match std::env::var(MASTER_PASSWORD_VARIABLE) {
+                Ok(master_password) => (master_password, false),
+                Err(_) => {
+                    (Self::read_master_password("New master password: "), true)
+                }
+            };
+        if master_password.is_empty() {
+            eprintln!("pwm: Master password can't be empty");
+            exit(1);
+        }
+        if prompted
+            && Self::read_master_password("Repeat master password: ")
+                != master_password
+        {
+            eprintln!("pwm: Master passwords don't match");
+            exit(1);
+        }
+        master_password
     }
 
     fn show_documentation() {
         let width = 12;
 
-        println!("usage: pwm <command>\n");
+        println!("usage: pwm [--insecure-ok] <command>\n");
         println!("Commands:");
         println!("  {:width$} Initializes password manager", "init");
         println!("  {:width$} Generates a password on the fly without", "gen");
@@ -187,7 +1509,56 @@ impl<I: Iterator<Item = String>> CommandLineInterface<I> {
         println!("  {:width$} Creates and stores a new password", "new");
         println!("  {:width$} Lists all passwords", "list");
         println!("  {:width$} Recovers the value of a password", "get");
-        println!("  {:width$} Removes a password", "rm, remove");
+        println!(
+            "  {:width$} Updates a password creating a new version",
+            "update"
+        );
+        println!("  {:width$} Lists every version of a password", "history");
+        println!(
+            "  {:width$} Shows what changed between two versions",
+            "diff"
+        );
+        println!(
+            "  {:width$} Generates a one-time code for a password",
+            "otp"
+        );
+        println!("  {:width$} Estimates the strength of a password", "check");
+        println!(
+            "  {:width$} Checks passwords for breaches and reuse",
+            "audit"
+        );
+        println!(
+            "  {:width$} Shows or sets the maximum age of passwords",
+            "policy"
+        );
+        println!(
+            "  {:width$} Lists passwords older than their policy",
+            "stale"
+        );
+        println!(
+            "  {:width$} Generates a new value with saved settings",
+            "rotate"
+        );
+        println!(
+            "  {:width$} Makes an old version of a password current",
+            "rollback"
+        );
+        println!("  {:width$} Deletes old versions of passwords", "prune");
+        println!("  {:width$} Renames a password with all its versions", "mv");
+        println!("  {:width$} Copies a password with all its versions", "cp");
+        println!(
+            "  {:width$} Moves a password to the trash, or deletes it",
+            "rm, remove"
+        );
+        println!(
+            "  {:width$} Lists, restores or empties removed passwords",
+            "trash"
+        );
         println!("  {:width$} Shows this help", "help");
+        println!("\nOptions:");
+        println!(
+            "  {:width$} Opens a store that other users can access",
+            "--insecure-ok"
+        );
     }
 }
@@ -0,0 +1,99 @@
+use super::permissions;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Prefix of the temporary files written next to their target
+const TEMPORARY_PREFIX: &str = ".tmp-";
+
+/// Writes a file so that it holds either its old or its new contents, even
+/// after a crash: the contents go to a temporary file in the same folder,
+/// which is synced to disk and renamed over the target, and then the folder
+/// is synced so that the rename is not lost either.
+pub fn write_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let folder = parent(path);
+    let temporary =
+        folder.join(format!("{TEMPORARY_PREFIX}{:016x}", OsRng.next_u64()));
+
+    let result = write_synced(&temporary, contents)
+        .and_then(|()| fs::rename(&temporary, path))
+        .and_then(|()| sync_folder(folder));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    result
+}
+
+/// Copies a file, only accessible by the current user, and syncs the copy
+/// to disk
+pub fn copy_file(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::copy(source, destination)?;
+    permissions::restrict_file(destination)?;
+    File::open(destination)?.sync_all()
+}
+
+/// Renames a file or folder and syncs the folders it left and went to
+pub fn rename(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::rename(source, destination)?;
+    sync_folder(parent(destination))?;
+    if parent(source) != parent(destination) {
+        sync_folder(parent(source))?;
+    }
+    Ok(())
+}
+
+/// Makes the entries added to or removed from a folder durable. Folders
+/// can only be synced on Unix, elsewhere renames are left to the OS.
+pub fn sync_folder(folder: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        File::open(folder)?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = permissions::new_file_options().open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_contents() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("1");
+        write_file(&path, b"a much longer first value").unwrap();
+        write_file(&path, b"short").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"short");
+        assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn failures_leave_the_target_untouched() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("1");
+        write_file(&path, b"old").unwrap();
+
+        // A folder can't be renamed over a file
+        let target = folder.path().join("2");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("nested"), "").unwrap();
+        assert!(write_file(&target, b"new").is_err());
+        assert!(write_file(&folder.path().join("missing/1"), b"new").is_err());
+
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(fs::read_dir(folder.path()).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,80 @@
+mod breaches;
+mod reuse;
+
+use super::{GetFlags, PasswordRepository, RepositoryError};
+pub use breaches::BreachList;
+pub use reuse::{ReuseGroup, ReuseKind, ReuseReport, VersionReuse};
+use serde::Serialize;
+
+/// Stored password version that appears in a breach list
+#[derive(Serialize, PartialEq, Debug)]
+pub struct Breach {
+    pub name: String,
+    pub version: u32,
+    /// Times the value was seen in breaches
+    pub occurrences: u64,
+}
+
+/// Results of the audits that were requested, for the JSON output
+#[derive(Serialize, Default)]
+pub struct AuditReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaches: Option<Vec<Breach>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reuse: Option<ReuseReport>,
+}
+
+/// Checks the latest version of every password against the breach list,
+/// or every version with `all_versions`
+pub fn find_breaches(
+    repository: &PasswordRepository,
+    breaches: &BreachList,
+    all_versions: bool,
+) -> Result<Vec<Breach>, RepositoryError> {
+    let mut found = Vec::new();
+    for name in repository.list()? {
+        let mut versions = repository.versions(&name)?;
+        if !all_versions {
+            versions = versions.split_off(versions.len().saturating_sub(1));
+        }
+
+        for version in versions {
+            let flags = GetFlags {
+                version: Some(version),
+                ..GetFlags::new()
+            };
+            let password = repository.get(&name, flags)?;
+            let occurrences =
+                breaches.occurrences(password.password().value())?;
+            if occurrences > 0 {
+                found.push(Breach {
+                    name: name.to_string(),
+                    version,
+                    occurrences,
+                });
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Groups passwords whose latest values are identical or similar, and
+/// finds the ones that went back to one of their older values
+pub fn find_reuse(
+    repository: &PasswordRepository,
+) -> Result<ReuseReport, RepositoryError> {
+    let mut entries = Vec::new();
+    for name in repository.list()? {
+        let mut values = Vec::new();
+        for version in repository.versions(&name)? {
+            let flags = GetFlags {
+                version: Some(version),
+                ..GetFlags::new()
+            };
+            let password = repository.get(&name, flags)?;
+            values.push((version, password.password().value().to_string()));
+        }
+        entries.push((name.to_string(), values));
+    }
+    Ok(ReuseReport::new(&entries))
+}
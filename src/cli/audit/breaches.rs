@@ -0,0 +1,195 @@
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Hex digits of the SHA-1 prefix used to name range files
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/// Locally downloaded Have I Been Pwned password hashes. Either a single
+/// file with `HASH:COUNT` lines ordered by hash, or a directory of range
+/// files named after the first five hex digits of the hash and holding
+/// `SUFFIX:COUNT` lines, as written by the HIBP downloader. Files are
+/// searched on disk and never loaded in memory.
+pub enum BreachList {
+    HashFile(PathBuf),
+    RangeDirectory(PathBuf),
+}
+
+impl BreachList {
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        if path.metadata()?.is_dir() {
+            Ok(BreachList::RangeDirectory(path))
+        } else {
+            Ok(BreachList::HashFile(path))
+        }
+    }
+
+    /// Number of times the password appears in breaches, zero if it was
+    /// never seen
+    pub fn occurrences(&self, password: &str) -> io::Result<u64> {
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+
+        match self {
+            BreachList::HashFile(path) => search(path, &hash),
+            BreachList::RangeDirectory(directory) => {
+                let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+                let mut path = directory.join(format!("{prefix}.txt"));
+                if !path.exists() {
+                    path = directory.join(prefix);
+                }
+                search(&path, suffix)
+            }
+        }
+    }
+}
+
+/// Binary searches a file of `HASH:COUNT` lines ordered by hash. Every
+/// probe reads the first line that starts at or after an offset, so lines
+/// can have any length.
+fn search(path: &Path, hash: &str) -> io::Result<u64> {
+    let file = File::open(path)?;
+    let mut low = 0;
+    let mut high = file.metadata()?.len();
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let Some((line, end)) = line_at_or_after(&file, middle)? else {
+            high = middle;
+            continue;
+        };
+        let (line_hash, count) = parse_line(path, &line)?;
+        match compare_hashes(line_hash, hash) {
+            Ordering::Less => low = end,
+            Ordering::Greater => high = middle,
+            Ordering::Equal => return Ok(count),
+        }
+    }
+    Ok(0)
+}
+
+/// First line that starts at or after the offset and the offset of the
+/// line that follows it
+fn line_at_or_after(
+    mut file: &File,
+    offset: u64,
+) -> io::Result<Option<(String, u64)>> {
+    let start = offset.saturating_sub(1);
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut position = start;
+
+    // Unless the offset is the start of the file, the character before it
+    // tells whether a line starts there, otherwise skip to the next one
+    if offset > 0 {
+        let mut skipped = Vec::new();
+        position += reader.read_until(b'\n', &mut skipped)? as u64;
+    }
+    let read = reader.read_line(&mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    Ok(Some((line, position + read as u64)))
+}
+
+fn parse_line<'a>(path: &Path, line: &'a str) -> io::Result<(&'a str, u64)> {
+    line.trim_end()
+        .split_once(':')
+        .and_then(|(hash, count)| Some((hash, count.parse().ok()?)))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "malformed line in {}: {}",
+                    path.display(),
+                    line.trim_end()
+                ),
+            )
+        })
+}
+
+fn compare_hashes(line_hash: &str, hash: &str) -> Ordering {
+    line_hash
+        .bytes()
+        .map(|byte| byte.to_ascii_uppercase())
+        .cmp(hash.bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn sha1_hex(password: &str) -> String {
+        Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect()
+    }
+
+    fn hash_lines(passwords: &[&str]) -> Vec<String> {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .enumerate()
+            .map(|(index, password)| {
+                format!("{}:{}", sha1_hex(password), index * 1000 + 1)
+            })
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn search_hash_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pwned.txt");
+        let passwords: Vec<String> =
+            (0..500).map(|index| format!("password{index}")).collect();
+        let passwords: Vec<&str> =
+            passwords.iter().map(String::as_str).collect();
+        fs::write(&path, hash_lines(&passwords).join("\r\n") + "\r\n")
+            .unwrap();
+
+        let list = BreachList::open(&path).unwrap();
+        for (index, password) in passwords.iter().enumerate() {
+            assert_eq!(
+                list.occurrences(password).unwrap(),
+                index as u64 * 1000 + 1
+            );
+        }
+        assert_eq!(list.occurrences("not breached").unwrap(), 0);
+    }
+
+    #[test]
+    fn search_range_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let hash = sha1_hex("hunter2");
+        let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+        let range = format!(
+            "0000000000000000000000000000000000A:3\n{}:17\nFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF:5\n",
+            suffix.to_lowercase()
+        );
+        fs::write(directory.path().join(format!("{prefix}.txt")), range)
+            .unwrap();
+
+        let list = BreachList::open(directory.path()).unwrap();
+        assert_eq!(list.occurrences("hunter2").unwrap(), 17);
+        assert!(list.occurrences("other").is_err());
+    }
+
+    #[test]
+    fn malformed_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("pwned.txt");
+        fs::write(&path, "not a hash list\n").unwrap();
+        let list = BreachList::open(&path).unwrap();
+        let error = list.occurrences("password").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
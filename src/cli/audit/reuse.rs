@@ -0,0 +1,335 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Values closer than this number of edits are considered near-identical
+const MAX_EDIT_DISTANCE: usize = 2;
+
+/// Values shorter than this are only compared for equality, as a couple
+/// of edits would make any two of them look alike
+const MIN_SIMILAR_LENGTH: usize = 6;
+
+/// Shortest base kept when stripping a trailing number, so that values
+/// that are mostly digits are not grouped together
+const MIN_BASE_LENGTH: usize = 3;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReuseKind {
+    Identical,
+    /// A small edit distance or the same base with another number
+    Similar,
+}
+
+impl std::fmt::Display for ReuseKind {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            ReuseKind::Identical => write!(formatter, "identical"),
+            ReuseKind::Similar => write!(formatter, "similar"),
+        }
+    }
+}
+
+/// Passwords whose latest values are identical or similar to each other
+#[derive(Serialize, PartialEq, Debug)]
+pub struct ReuseGroup {
+    pub kind: ReuseKind,
+    pub names: Vec<String>,
+}
+
+/// Password whose latest value repeats one of its older versions
+#[derive(Serialize, PartialEq, Debug)]
+pub struct VersionReuse {
+    pub name: String,
+    pub latest: u32,
+    pub version: u32,
+    pub kind: ReuseKind,
+}
+
+#[derive(Serialize, PartialEq, Debug, Default)]
+pub struct ReuseReport {
+    pub groups: Vec<ReuseGroup>,
+    pub versions: Vec<VersionReuse>,
+}
+
+impl ReuseReport {
+    /// Builds the report from every version of every password, as
+    /// `(name, [(version, value)])` with versions in ascending order
+    pub fn new(entries: &[(String, Vec<(u32, String)>)]) -> Self {
+        let latest: Vec<(&str, &str)> = entries
+            .iter()
+            .filter_map(|(name, versions)| {
+                let (_, value) = versions.last()?;
+                Some((name.as_str(), value.as_str()))
+            })
+            .collect();
+
+        ReuseReport {
+            groups: reuse_groups(&latest),
+            versions: entries
+                .iter()
+                .filter_map(|(name, versions)| version_reuse(name, versions))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty() && self.versions.is_empty()
+    }
+}
+
+impl std::fmt::Display for ReuseReport {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let mut lines = Vec::new();
+        for group in &self.groups {
+            lines.push(format!(
+                "{} values: {}",
+                group.kind,
+                group.names.join(", ")
+            ));
+        }
+        for reuse in &self.versions {
+            lines.push(format!(
+                "{} version {} is {} to version {}",
+                reuse.name, reuse.latest, reuse.kind, reuse.version
+            ));
+        }
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+fn reuse_groups(latest: &[(&str, &str)]) -> Vec<ReuseGroup> {
+    let mut groups = Vec::new();
+
+    // Identical values first, then one representative of each value is
+    // compared with the others
+    let mut identical: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut values = Vec::new();
+    for (name, value) in latest {
+        let names = identical.entry(value).or_default();
+        if names.is_empty() {
+            values.push(*value);
+        }
+        names.push(name);
+    }
+    for value in &values {
+        if identical[value].len() > 1 {
+            groups.push(ReuseGroup {
+                kind: ReuseKind::Identical,
+                names: identical[value]
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect(),
+            });
+        }
+    }
+
+    // Near-identical values are grouped transitively
+    let mut parents: Vec<usize> = (0..values.len()).collect();
+    for first in 0..values.len() {
+        for second in first + 1..values.len() {
+            if are_similar(values[first], values[second]) {
+                let first_root = root(&mut parents, first);
+                let second_root = root(&mut parents, second);
+                parents[second_root] = first_root;
+            }
+        }
+    }
+    let mut similar: HashMap<usize, Vec<String>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        let group_root = root(&mut parents, index);
+        let names = similar.entry(group_root).or_default();
+        if names.is_empty() {
+            roots.push(group_root);
+        }
+        names.extend(identical[value].iter().map(|n| n.to_string()));
+    }
+    for group_root in roots {
+        let names = similar.remove(&group_root).unwrap_or_default();
+        let distinct_values = (0..values.len())
+            .filter(|index| root(&mut parents, *index) == group_root)
+            .count();
+        if distinct_values > 1 {
+            groups.push(ReuseGroup {
+                kind: ReuseKind::Similar,
+                names,
+            });
+        }
+    }
+    groups
+}
+
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// Closest older version to the latest value, if any is identical or
+/// similar to it. Only versions from before the value last changed count,
+/// as one-time password counters and field edits keep the value.
+fn version_reuse(
+    name: &str,
+    versions: &[(u32, String)],
+) -> Option<VersionReuse> {
+    let ((latest, value), older) = versions.split_last()?;
+    let changed = older
+        .iter()
+        .rposition(|(_, old)| old != value)
+        .map_or(0, |position| position + 1);
+    let older = &older[..changed];
+    let kind_of = |old: &str| {
+        if old == value {
+            Some(ReuseKind::Identical)
+        } else if are_similar(old, value) {
+            Some(ReuseKind::Similar)
+        } else {
+            None
+        }
+    };
+
+    let identical = older
+        .iter()
+        .rev()
+        .find(|(_, old)| kind_of(old) == Some(ReuseKind::Identical));
+    let reused = identical.or_else(|| {
+        older.iter().rev().find(|(_, old)| kind_of(old).is_some())
+    })?;
+    Some(VersionReuse {
+        name: name.to_string(),
+        latest: *latest,
+        version: reused.0,
+        kind: kind_of(&reused.1)?,
+    })
+}
+
+/// Whether two different values are a small edit apart or share a base
+/// followed by a different number, such as `Summer2023` and `Summer2024`
+fn are_similar(first: &str, second: &str) -> bool {
+    let first: Vec<char> = first.chars().collect();
+    let second: Vec<char> = second.chars().collect();
+    if first.len().min(second.len()) < MIN_SIMILAR_LENGTH {
+        return false;
+    }
+
+    let base = |value: &[char]| {
+        let digits = value
+            .iter()
+            .rev()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        value[..value.len() - digits].to_vec()
+    };
+    let (first_base, second_base) = (base(&first), base(&second));
+    if first_base.len() >= MIN_BASE_LENGTH
+        && first_base.len() < first.len()
+        && second_base.len() < second.len()
+        && first_base == second_base
+    {
+        return true;
+    }
+
+    first.len().abs_diff(second.len()) <= MAX_EDIT_DISTANCE
+        && edit_distance(&first, &second) <= MAX_EDIT_DISTANCE
+}
+
+/// Levenshtein distance between two values
+fn edit_distance(first: &[char], second: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=second.len()).collect();
+    for (i, first_char) in first.iter().enumerate() {
+        let mut current = vec![i + 1; second.len() + 1];
+        for (j, second_char) in second.iter().enumerate() {
+            let substitution =
+                previous[j] + usize::from(first_char != second_char);
+            current[j + 1] =
+                substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[second.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, values: &[&str]) -> (String, Vec<(u32, String)>) {
+        let versions = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (index as u32 + 1, value.to_string()))
+            .collect();
+        (name.to_string(), versions)
+    }
+
+    #[test]
+    fn similarity() {
+        assert_eq!(edit_distance(&['a', 'b', 'c'], &['a', 'c']), 1);
+        assert!(are_similar("Summer2023", "Summer2024"));
+        assert!(are_similar("correct-horse", "correct-horse!"));
+        assert!(are_similar("hunter9", "hunter10000"));
+        assert!(!are_similar("x7#Kq2Lm9", "Vb4!pT8wZ"));
+        assert!(!are_similar("abc1", "abc2"));
+        assert!(!are_similar("12345678", "12349999"));
+    }
+
+    #[test]
+    fn groups_and_versions() {
+        let report = ReuseReport::new(&[
+            entry("bank", &["Secret-Value-1"]),
+            entry("email", &["Secret-Value-1"]),
+            entry("github", &["Secret-Value-2"]),
+            entry("random", &["x7#Kq2Lm9Vb4!pT8"]),
+            entry("work", &["Winter2023!", "Other-Value", "Winter2023!"]),
+            entry("vpn", &["Spring2023", "Spring2024"]),
+            entry("otp", &["Kept-Value-1", "Kept-Value-1", "Kept-Value-1"]),
+        ]);
+
+        assert_eq!(
+            report.groups,
+            [
+                ReuseGroup {
+                    kind: ReuseKind::Identical,
+                    names: vec!["bank".to_string(), "email".to_string()],
+                },
+                ReuseGroup {
+                    kind: ReuseKind::Similar,
+                    names: vec![
+                        "bank".to_string(),
+                        "email".to_string(),
+                        "github".to_string()
+                    ],
+                },
+            ]
+        );
+        assert_eq!(
+            report.versions,
+            [
+                VersionReuse {
+                    name: "work".to_string(),
+                    latest: 3,
+                    version: 1,
+                    kind: ReuseKind::Identical,
+                },
+                VersionReuse {
+                    name: "vpn".to_string(),
+                    latest: 2,
+                    version: 1,
+                    kind: ReuseKind::Similar,
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "identical values: bank, email\nsimilar values: bank, email, github\nwork version 3 is identical to version 1\nvpn version 2 is similar to version 1"
+        );
+    }
+}
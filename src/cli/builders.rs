@@ -1,16 +1,28 @@
+mod error;
 mod length;
+mod passphrase;
+mod policy;
 
 use super::Password;
+pub use error::BuilderError;
 use length::PasswordLengths;
-use rand;
+pub(crate) use passphrase::{wordlist, MAX_WORDS};
+pub use passphrase::{Capitalization, PassphraseOptions};
+pub use policy::{CharacterClass, CharacterPolicy};
+use rand::rngs::OsRng;
 use rand::seq::SliceRandom;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
 pub struct PasswordBuilder {
-    min_length: u8,
-    max_length: u8,
+    min_length: u16,
+    max_length: u16,
     version: u8,
+    /// Generate diceware passphrases instead of random characters
+    passphrase: Option<PassphraseOptions>,
+    policy: CharacterPolicy,
 }
 
 impl Default for PasswordBuilder {
@@ -19,6 +31,8 @@ impl Default for PasswordBuilder {
             min_length: 8,
             max_length: 16,
             version: 1,
+            passphrase: None,
+            policy: CharacterPolicy::default(),
         }
     }
 }
@@ -28,44 +42,88 @@ impl PasswordBuilder {
         PasswordBuilder::default()
     }
 
-    pub fn min_length(mut self, min_length: u8) -> Self {
-        if min_length < 1 || min_length > self.max_length {
-            panic!("Minimum length should be between 0 and maximum length");
-        }
+    /// Lengths are only validated when building, so the minimum and the
+    /// maximum can be set in any order
+    pub fn min_length(mut self, min_length: u16) -> Self {
         self.min_length = min_length;
         self
     }
 
-    pub fn max_length(mut self, max_length: u8) -> Self {
-        if self.min_length > max_length {
-            panic!("Maximum length should be greater than minimum length");
-        }
+    pub fn max_length(mut self, max_length: u16) -> Self {
         self.max_length = max_length;
         self
     }
 
+    /// Generates passwords of exactly this length
+    pub fn length(self, length: u16) -> Self {
+        self.min_length(length).max_length(length)
+    }
+
     pub fn version(mut self, version: u8) -> Self {
         self.version = version;
         self
     }
+
+    pub fn passphrase(mut self, passphrase: PassphraseOptions) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    pub fn passphrase_options(&self) -> Option<&PassphraseOptions> {
+        self.passphrase.as_ref()
+    }
+
+    pub fn character_policy(mut self, policy: CharacterPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &CharacterPolicy {
+        &self.policy
+    }
 }
 
 impl PasswordBuilder {
-    pub fn build(&self, name: String) -> Password {
-        Password::new(name, self.build_secure_password())
+    pub fn build(&self, name: String) -> Result<Password, BuilderError> {
+        Ok(Password::new(name, self.build_secure_password()?))
+    }
+
+    pub fn build_secure_password(&self) -> Result<String, BuilderError> {
+        if let Some(passphrase) = &self.passphrase {
+            return passphrase.build();
+        }
+        self.validate()?;
+        let password_lengths = self.gen_password_lengths()?;
+        Ok(self.gen_password_from_lengths(password_lengths))
     }
 
-    pub fn build_secure_password(&self) -> String {
-        let password_lengths = self.gen_password_lengths();
-        self.gen_password_from_lengths(password_lengths)
+    fn validate(&self) -> Result<(), BuilderError> {
+        if self.min_length < 1 {
+            return Err(BuilderError::ZeroLength);
+        }
+        if self.min_length > self.max_length {
+            return Err(BuilderError::InvalidLengthRange {
+                min_length: self.min_length,
+                max_length: self.max_length,
+            });
+        }
+        self.policy.validate()?;
+        if self.policy.minimum_length() > self.max_length as u32 {
+            return Err(BuilderError::MinimumsExceedLength {
+                required: self.policy.minimum_length(),
+                max_length: self.max_length as u32,
+            });
+        }
+        Ok(())
     }
 
-    fn gen_password_lengths(&self) -> PasswordLengths {
-        let length_difference = self.max_length - self.min_length + 1;
-        let total_length =
-            (rand::random::<u8>() % length_difference) + self.min_length;
-        let password_lengths = PasswordLengths::new(total_length);
-        password_lengths
+    /// Draws the length uniformly between the bounds, raising the minimum
+    /// to what the character policy needs
+    fn gen_password_lengths(&self) -> Result<PasswordLengths, BuilderError> {
+        let min_length =
+            self.min_length.max(self.policy.minimum_length() as u16);
+        let total_length = OsRng.gen_range(min_length..=self.max_length);
+        PasswordLengths::new(total_length, &self.policy)
     }
 
     fn gen_password_from_lengths(
@@ -74,45 +132,32 @@ impl PasswordBuilder {
     ) -> String {
         let mut password_characters = Vec::new();
         for _ in 0..password_lengths.lower() {
-            password_characters.push(self.gen_lowercase_letter());
+            password_characters
+                .push(self.gen_character(CharacterClass::Lowercase));
         }
         for _ in 0..password_lengths.upper() {
-            password_characters.push(self.gen_uppercase_letter());
+            password_characters
+                .push(self.gen_character(CharacterClass::Uppercase));
         }
         for _ in 0..password_lengths.punctuation() {
-            password_characters.push(self.gen_punctuation());
+            password_characters
+                .push(self.gen_character(CharacterClass::Symbols));
         }
         for _ in 0..password_lengths.numbers() {
-            password_characters.push(self.gen_digit());
+            password_characters
+                .push(self.gen_character(CharacterClass::Digits));
         }
 
-        password_characters.shuffle(&mut rand::thread_rng());
+        password_characters.shuffle(&mut OsRng);
         String::from_iter(password_characters)
     }
 
-    fn gen_lowercase_letter(&self) -> char {
-        let unicode_scalar_value: u8 = rand::thread_rng().gen_range(97..=122);
-        unicode_scalar_value as char
-    }
-
-    fn gen_uppercase_letter(&self) -> char {
-        let unicode_scalar_value: u8 = rand::thread_rng().gen_range(65..=90);
-        unicode_scalar_value as char
-    }
-
-    fn gen_punctuation(&self) -> char {
-        let mut rng = rand::thread_rng();
-        let unicode_scalar_value: u8 = if rng.gen::<bool>() {
-            rng.gen_range(33..=47)
-        } else {
-            rng.gen_range(58..=64)
-        };
-        unicode_scalar_value as char
-    }
-
-    fn gen_digit(&self) -> char {
-        let unicode_scalar_value: u8 = rand::thread_rng().gen_range(48..=57);
-        unicode_scalar_value as char
+    fn gen_character(&self, class: CharacterClass) -> char {
+        *self
+            .policy
+            .alphabet(class)
+            .choose(&mut OsRng)
+            .expect("Validated policies have characters in every class")
     }
 }
 
@@ -159,7 +204,8 @@ mod tests {
     #[test]
     fn secure_password() {
         let password_creator = PasswordBuilder::new();
-        let password = password_creator.build("test_password".to_string());
+        let password =
+            password_creator.build("test_password".to_string()).unwrap();
         assert!(has_lowercase(&password));
         assert!(has_uppercase(&password));
         assert!(has_punctuation(&password));
@@ -169,19 +215,127 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn invalid_min_length() {
         let password_creator = PasswordBuilder::new().min_length(0);
-        password_creator.build("test_password".to_string());
+        assert_eq!(
+            password_creator.build("test_password".to_string()),
+            Err(BuilderError::ZeroLength)
+        );
     }
 
     #[test]
-    #[should_panic]
     fn invalid_max_length() {
         let mut password_creator = PasswordBuilder::new().min_length(1);
         password_creator = password_creator
             .clone()
             .max_length(password_creator.min_length - 1);
-        password_creator.build("test_password".to_string());
+        assert_eq!(
+            password_creator.build("test_password".to_string()),
+            Err(BuilderError::InvalidLengthRange {
+                min_length: 1,
+                max_length: 0
+            })
+        );
+    }
+
+    #[test]
+    fn short_passwords() {
+        let lowercase_only = [
+            CharacterClass::Uppercase,
+            CharacterClass::Symbols,
+            CharacterClass::Digits,
+        ]
+        .into_iter()
+        .fold(CharacterPolicy::new(), |policy, class| {
+            policy.enable(class, false)
+        });
+        for length in 1..=3 {
+            let password = PasswordBuilder::new()
+                .length(length)
+                .character_policy(lowercase_only.clone())
+                .build("test_password".to_string())
+                .unwrap();
+            assert_eq!(password.value().len(), length as usize);
+            assert!(has_lowercase(&password));
+        }
+    }
+
+    #[test]
+    fn lengths_are_uniform() {
+        let password_creator =
+            PasswordBuilder::new().min_length(8).max_length(11);
+        let samples = 4000;
+        let mut counts = [0u32; 4];
+        for _ in 0..samples {
+            let password =
+                password_creator.build("test_password".to_string()).unwrap();
+            counts[password.value().len() - 8] += 1;
+        }
+
+        // Chi-squared with 3 degrees of freedom, a uniform distribution only
+        // goes above 25.9 once every 100000 runs
+        let expected = samples as f64 / counts.len() as f64;
+        let chi_squared: f64 = counts
+            .iter()
+            .map(|count| (*count as f64 - expected).powi(2) / expected)
+            .sum();
+        assert!(chi_squared < 25.9, "{counts:?}");
+    }
+
+    #[test]
+    fn long_password() {
+        let password_creator = PasswordBuilder::new().length(1024);
+        let password =
+            password_creator.build("test_password".to_string()).unwrap();
+        assert_eq!(password.value().len(), 1024);
+        assert!(has_lowercase(&password));
+        assert!(has_uppercase(&password));
+        assert!(has_punctuation(&password));
+        assert!(has_numbers(&password));
+    }
+
+    #[test]
+    fn password_follows_policy() {
+        let policy = CharacterPolicy::new()
+            .enable(CharacterClass::Uppercase, false)
+            .minimum(CharacterClass::Digits, 4)
+            .symbols("@#")
+            .exclude("xyz")
+            .avoid_ambiguous(true);
+        let password_creator = PasswordBuilder::new().character_policy(policy);
+
+        for _ in 0..50 {
+            let password =
+                password_creator.build("test_password".to_string()).unwrap();
+            assert!(!has_uppercase(&password));
+            assert!(
+                password
+                    .value()
+                    .chars()
+                    .filter(char::is_ascii_digit)
+                    .count()
+                    >= 4
+            );
+            assert!(password
+                .value()
+                .chars()
+                .filter(char::is_ascii_punctuation)
+                .all(|c| c == '@' || c == '#'));
+            assert!(!password.value().contains(['x', 'y', 'z', '0', 'l']));
+        }
+    }
+
+    #[test]
+    fn minimums_exceeding_max_length() {
+        let policy =
+            CharacterPolicy::new().minimum(CharacterClass::Digits, 20);
+        let password_creator = PasswordBuilder::new().character_policy(policy);
+        assert_eq!(
+            password_creator.build_secure_password(),
+            Err(BuilderError::MinimumsExceedLength {
+                required: 23,
+                max_length: 16
+            })
+        );
     }
 }
@@ -0,0 +1,59 @@
+use super::passphrase::MAX_WORDS;
+use super::policy::CharacterClass;
+
+#[derive(Debug, PartialEq)]
+pub enum BuilderError {
+    /// Passwords need at least one character
+    ZeroLength,
+    /// The minimum length is greater than the maximum length
+    InvalidLengthRange { min_length: u16, max_length: u16 },
+    /// Every character class is disabled
+    NoCharacterClass,
+    /// Exclusions leave an enabled class without any character
+    EmptyCharacterClass(CharacterClass),
+    /// The minimum number of characters per class don't fit in the maximum
+    /// password length
+    MinimumsExceedLength { required: u32, max_length: u32 },
+    /// Passphrases need between one and `MAX_WORDS` words
+    WordCount(usize),
+}
+
+impl std::fmt::Display for BuilderError {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            BuilderError::ZeroLength => {
+                write!(formatter, "the minimum length must be at least 1")
+            }
+            BuilderError::InvalidLengthRange {
+                min_length,
+                max_length,
+            } => write!(
+                formatter,
+                "the minimum length {min_length} is greater than the maximum length {max_length}"
+            ),
+            BuilderError::NoCharacterClass => {
+                write!(formatter, "at least one character class is needed")
+            }
+            BuilderError::EmptyCharacterClass(class) => write!(
+                formatter,
+                "no {class} characters are left after the exclusions"
+            ),
+            BuilderError::MinimumsExceedLength {
+                required,
+                max_length,
+            } => write!(
+                formatter,
+                "the character minimums need {required} characters but the maximum length is {max_length}"
+            ),
+            BuilderError::WordCount(words) => write!(
+                formatter,
+                "passphrases need 1 to {MAX_WORDS} words, not {words}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {}
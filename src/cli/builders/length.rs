@@ -1,60 +1,82 @@
-use rand::seq::SliceRandom;
+use super::error::BuilderError;
+use super::policy::{CharacterClass, CharacterPolicy};
+use rand::rngs::OsRng;
 use rand::Rng;
 
 /// Lengths for the different characters the password will need
 pub struct PasswordLengths {
     /// Number of lowercase letters
-    lower: u8,
+    lower: u16,
     /// Number of uppercase letters
-    upper: u8,
+    upper: u16,
     /// Number of punctuation characters
-    punctuation: u8,
+    punctuation: u16,
     /// Number of numbers
-    numbers: u8,
+    numbers: u16,
 }
 
 impl PasswordLengths {
-    pub fn new(total_length: u8) -> Self {
-        let mut rng = rand::thread_rng();
-        let mut lengths = Vec::new();
-
-        if total_length < 4 {
-            panic!("Total length must be at least 4");
+    /// Splits the total length between the enabled character classes,
+    /// giving each of them at least its minimum. Every other character is
+    /// assigned to a class with a probability proportional to the size of
+    /// its alphabet, as if it was drawn from all the allowed characters.
+    pub fn new(
+        total_length: u16,
+        policy: &CharacterPolicy,
+    ) -> Result<Self, BuilderError> {
+        if (total_length as u32) < policy.minimum_length() {
+            return Err(BuilderError::MinimumsExceedLength {
+                required: policy.minimum_length(),
+                max_length: total_length as u32,
+            });
         }
 
-        (0..3)
-            .map(|_| rng.gen_range(1..(total_length - 3)))
-            .for_each(|length| lengths.push(length));
-
-        let sum: u8 = lengths.iter().sum();
+        let alphabet_sizes = CharacterClass::ALL.map(|class| {
+            if policy.is_enabled(class) {
+                policy.alphabet(class).len()
+            } else {
+                0
+            }
+        });
+        let pool_size: usize = alphabet_sizes.iter().sum();
+        if pool_size == 0 {
+            return Err(BuilderError::NoCharacterClass);
+        }
 
-        if sum >= total_length {
-            return PasswordLengths::new(total_length);
+        let mut lengths =
+            CharacterClass::ALL.map(|class| policy.minimum_of(class));
+        let remaining = total_length as u32 - policy.minimum_length();
+        for _ in 0..remaining {
+            let mut character = OsRng.gen_range(0..pool_size);
+            let mut class = 0;
+            while character >= alphabet_sizes[class] {
+                character -= alphabet_sizes[class];
+                class += 1;
+            }
+            lengths[class] += 1;
         }
-        lengths.push(total_length - sum);
-        lengths.shuffle(&mut rng);
 
-        PasswordLengths {
+        Ok(PasswordLengths {
             lower: lengths[0],
             upper: lengths[1],
             punctuation: lengths[2],
             numbers: lengths[3],
-        }
+        })
     }
 
-    pub fn lower(&self) -> u8 {
+    pub fn lower(&self) -> u16 {
         self.lower
     }
 
-    pub fn upper(&self) -> u8 {
+    pub fn upper(&self) -> u16 {
         self.upper
     }
 
-    pub fn punctuation(&self) -> u8 {
+    pub fn punctuation(&self) -> u16 {
         self.punctuation
     }
 
-    pub fn numbers(&self) -> u8 {
+    pub fn numbers(&self) -> u16 {
         self.numbers
     }
 }
@@ -65,11 +87,101 @@ mod tests {
 
     #[test]
     fn test_new() {
-        let lengths = PasswordLengths::new(10);
+        let lengths =
+            PasswordLengths::new(10, &CharacterPolicy::default()).unwrap();
         assert!(lengths.lower > 0);
         assert!(lengths.upper > 0);
         assert!(lengths.punctuation > 0);
         assert!(lengths.numbers > 0);
-        assert!(lengths.lower + lengths.upper + lengths.punctuation + lengths.numbers == 10);
+        assert!(
+            lengths.lower
+                + lengths.upper
+                + lengths.punctuation
+                + lengths.numbers
+                == 10
+        );
+    }
+
+    #[test]
+    fn respects_policy() {
+        let policy = CharacterPolicy::new()
+            .enable(CharacterClass::Symbols, false)
+            .minimum(CharacterClass::Digits, 5);
+        for _ in 0..100 {
+            let lengths = PasswordLengths::new(8, &policy).unwrap();
+            assert_eq!(lengths.punctuation, 0);
+            assert!(lengths.numbers >= 5);
+            assert!(lengths.lower >= 1);
+            assert!(lengths.upper >= 1);
+            assert_eq!(lengths.lower + lengths.upper + lengths.numbers, 8);
+        }
+    }
+
+    #[test]
+    fn short_lengths() {
+        let policy = CharacterPolicy::new()
+            .enable(CharacterClass::Uppercase, false)
+            .enable(CharacterClass::Symbols, false)
+            .enable(CharacterClass::Digits, false);
+        let lengths = PasswordLengths::new(1, &policy).unwrap();
+        assert_eq!(lengths.lower, 1);
+
+        let policy = CharacterClass::ALL
+            .into_iter()
+            .fold(CharacterPolicy::new(), |policy, class| {
+                policy.minimum(class, 0)
+            });
+        for total_length in 1..=3 {
+            let lengths = PasswordLengths::new(total_length, &policy).unwrap();
+            assert_eq!(
+                lengths.lower
+                    + lengths.upper
+                    + lengths.punctuation
+                    + lengths.numbers,
+                total_length
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_policies() {
+        let policy = CharacterPolicy::new().minimum(CharacterClass::Digits, 5);
+        assert!(matches!(
+            PasswordLengths::new(4, &policy),
+            Err(BuilderError::MinimumsExceedLength { max_length: 4, .. })
+        ));
+
+        let no_classes = CharacterClass::ALL
+            .into_iter()
+            .fold(CharacterPolicy::new(), |policy, class| {
+                policy.enable(class, false)
+            });
+        assert!(matches!(
+            PasswordLengths::new(4, &no_classes),
+            Err(BuilderError::NoCharacterClass)
+        ));
+    }
+
+    #[test]
+    fn split_is_proportional_to_alphabets() {
+        // 26 lowercase letters and 10 digits, no minimums
+        let policy = CharacterPolicy::new()
+            .enable(CharacterClass::Uppercase, false)
+            .enable(CharacterClass::Symbols, false)
+            .minimum(CharacterClass::Lowercase, 0)
+            .minimum(CharacterClass::Digits, 0);
+        let samples = 1000;
+        let total_length = 36;
+        let mut lower = 0.0;
+        for _ in 0..samples {
+            let lengths = PasswordLengths::new(total_length, &policy).unwrap();
+            assert_eq!(lengths.lower + lengths.numbers, total_length);
+            lower += lengths.lower as f64;
+        }
+
+        // Binomial with n = 36000 and p = 26 / 36, the standard deviation
+        // is about 85 so this bound is above five of them
+        let expected = (samples * total_length as usize) as f64 * 26.0 / 36.0;
+        assert!((lower - expected).abs() < 450.0, "{lower} vs {expected}");
     }
 }
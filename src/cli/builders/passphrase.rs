@@ -0,0 +1,242 @@
+use super::error::BuilderError;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// Diceware word list with 7776 entries, one per roll of five dice, in the
+/// `<dice>\t<word>` format of the EFF lists. This is a stand-in built from
+/// common English words, not the curated EFF long list, which can replace
+/// it as is.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+/// Symbols that can be inserted in a passphrase, the same ones used for
+/// character passwords
+const SYMBOLS: &[u8] = b"!\"#$%&'()*+,-./:;<=>?@";
+
+/// Most words a passphrase can have, far more than any site accepts
+pub const MAX_WORDS: usize = 64;
+
+pub(crate) fn wordlist() -> &'static [&'static str] {
+    static WORDS: OnceLock<Vec<&'static str>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        WORDLIST
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .collect()
+    })
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Capitalization {
+    Lowercase,
+    Uppercase,
+    /// First letter of every word in uppercase
+    Title,
+    /// Every word is randomly lowercase or title case
+    Random,
+}
+
+impl std::str::FromStr for Capitalization {
+    type Err = String;
+
+    fn from_str(capitalization: &str) -> Result<Self, Self::Err> {
+        match capitalization {
+            "lower" => Ok(Capitalization::Lowercase),
+            "upper" => Ok(Capitalization::Uppercase),
+            "title" => Ok(Capitalization::Title),
+            "random" => Ok(Capitalization::Random),
+            _ => Err(format!(
+                "Unknown capitalization '{capitalization}', expected lower, upper, title or random"
+            )),
+        }
+    }
+}
+
+/// Settings of the diceware passphrase mode of the password builder
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct PassphraseOptions {
+    words: usize,
+    separator: String,
+    capitalization: Capitalization,
+    digit: bool,
+    symbol: bool,
+}
+
+impl Default for PassphraseOptions {
+    fn default() -> Self {
+        PassphraseOptions {
+            words: 6,
+            separator: String::from("-"),
+            capitalization: Capitalization::Lowercase,
+            digit: false,
+            symbol: false,
+        }
+    }
+}
+
+impl PassphraseOptions {
+    pub fn new() -> Self {
+        PassphraseOptions::default()
+    }
+
+    /// Number of words, only validated when building
+    pub fn words(mut self, words: usize) -> Self {
+        self.words = words;
+        self
+    }
+
+    pub fn separator(mut self, separator: String) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    pub fn capitalization(mut self, capitalization: Capitalization) -> Self {
+        self.capitalization = capitalization;
+        self
+    }
+
+    /// Appends a random digit to one of the words
+    pub fn digit(mut self, digit: bool) -> Self {
+        self.digit = digit;
+        self
+    }
+
+    /// Appends a random symbol to one of the words
+    pub fn symbol(mut self, symbol: bool) -> Self {
+        self.symbol = symbol;
+        self
+    }
+
+    pub fn build(&self) -> Result<String, BuilderError> {
+        if !(1..=MAX_WORDS).contains(&self.words) {
+            return Err(BuilderError::WordCount(self.words));
+        }
+        let mut rng = OsRng;
+        let mut passphrase: Vec<String> = (0..self.words)
+            .map(|_| {
+                let word = wordlist().choose(&mut rng).unwrap();
+                self.capitalize(word, &mut rng)
+            })
+            .collect();
+
+        if self.digit {
+            let position = rng.gen_range(0..self.words);
+            let digit = char::from(b'0' + rng.gen_range(0..10));
+            passphrase[position].push(digit);
+        }
+        if self.symbol {
+            let position = rng.gen_range(0..self.words);
+            let symbol = *SYMBOLS.choose(&mut rng).unwrap() as char;
+            passphrase[position].push(symbol);
+        }
+
+        Ok(passphrase.join(&self.separator))
+    }
+
+    fn capitalize<R: Rng>(&self, word: &str, rng: &mut R) -> String {
+        let title = || {
+            let mut characters = word.chars();
+            match characters.next() {
+                Some(first) => {
+                    first.to_uppercase().chain(characters).collect()
+                }
+                None => String::new(),
+            }
+        };
+        match self.capitalization {
+            Capitalization::Lowercase => word.to_string(),
+            Capitalization::Uppercase => word.to_uppercase(),
+            Capitalization::Title => title(),
+            Capitalization::Random if rng.gen::<bool>() => title(),
+            Capitalization::Random => word.to_string(),
+        }
+    }
+
+    /// Entropy of the generated passphrases, assuming the attacker knows
+    /// the word list and these settings
+    pub fn entropy_bits(&self) -> f64 {
+        let words = self.words as f64;
+        let mut bits = words * (words_count() as f64).log2();
+        if self.capitalization == Capitalization::Random {
+            bits += words;
+        }
+        if self.digit {
+            bits += (10.0 * words).log2();
+        }
+        if self.symbol {
+            bits += (SYMBOLS.len() as f64 * words).log2();
+        }
+        bits
+    }
+}
+
+fn words_count() -> usize {
+    wordlist().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn wordlist_has_one_word_per_dice_roll() {
+        let unique: HashSet<_> = wordlist().iter().collect();
+        assert_eq!(wordlist().len(), 7776);
+        assert_eq!(unique.len(), 7776);
+    }
+
+    #[test]
+    fn passphrase_words_and_separator() {
+        let passphrase = PassphraseOptions::new()
+            .words(5)
+            .separator(String::from("_"))
+            .capitalization(Capitalization::Title)
+            .build()
+            .unwrap();
+        let parts: Vec<&str> = passphrase.split('_').collect();
+        assert_eq!(parts.len(), 5);
+        for part in parts {
+            assert!(part.chars().next().unwrap().is_ascii_uppercase());
+            assert!(wordlist().contains(&part.to_lowercase().as_str()));
+        }
+    }
+
+    #[test]
+    fn passphrase_with_digit_and_symbol() {
+        let passphrase = PassphraseOptions::new()
+            .separator(String::from(" "))
+            .digit(true)
+            .symbol(true)
+            .build()
+            .unwrap();
+        assert!(passphrase.chars().any(|c| c.is_ascii_digit()));
+        assert!(passphrase.chars().any(|c| SYMBOLS.contains(&(c as u8))));
+    }
+
+    #[test]
+    fn word_counts_are_bounded() {
+        for words in [0, MAX_WORDS + 1, usize::MAX] {
+            assert_eq!(
+                PassphraseOptions::new().words(words).build(),
+                Err(BuilderError::WordCount(words))
+            );
+        }
+        let passphrase =
+            PassphraseOptions::new().words(MAX_WORDS).build().unwrap();
+        assert_eq!(passphrase.split('-').count(), MAX_WORDS);
+    }
+
+    #[test]
+    fn entropy() {
+        let options = PassphraseOptions::new();
+        assert!((options.entropy_bits() - 6.0 * 7776f64.log2()).abs() < 1e-9);
+        assert!(
+            options.clone().digit(true).entropy_bits()
+                > options.entropy_bits()
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use super::error::BuilderError;
+use serde::{Deserialize, Serialize};
+
+/// Characters that are easily confused with each other when read or typed
+const AMBIGUOUS: &str = "0Oo1lI|";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Symbols,
+    Digits,
+}
+
+impl CharacterClass {
+    pub const ALL: [CharacterClass; 4] = [
+        CharacterClass::Lowercase,
+        CharacterClass::Uppercase,
+        CharacterClass::Symbols,
+        CharacterClass::Digits,
+    ];
+
+    pub(super) fn index(&self) -> usize {
+        match self {
+            CharacterClass::Lowercase => 0,
+            CharacterClass::Uppercase => 1,
+            CharacterClass::Symbols => 2,
+            CharacterClass::Digits => 3,
+        }
+    }
+}
+
+impl std::fmt::Display for CharacterClass {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let name = match self {
+            CharacterClass::Lowercase => "lowercase",
+            CharacterClass::Uppercase => "uppercase",
+            CharacterClass::Symbols => "symbols",
+            CharacterClass::Digits => "digits",
+        };
+        write!(formatter, "{name}")
+    }
+}
+
+impl std::str::FromStr for CharacterClass {
+    type Err = String;
+
+    fn from_str(class: &str) -> Result<Self, Self::Err> {
+        CharacterClass::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_string() == class)
+            .ok_or_else(|| format!("Unknown character class '{class}'"))
+    }
+}
+
+/// Which characters generated passwords may contain
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct CharacterPolicy {
+    enabled: [bool; 4],
+    /// Minimum number of characters of each enabled class
+    minimums: [u16; 4],
+    symbols: Vec<char>,
+    excluded: Vec<char>,
+    avoid_ambiguous: bool,
+}
+
+impl Default for CharacterPolicy {
+    fn default() -> Self {
+        CharacterPolicy {
+            enabled: [true; 4],
+            minimums: [1; 4],
+            symbols: (33..=47u8).chain(58..=64).map(char::from).collect(),
+            excluded: Vec::new(),
+            avoid_ambiguous: false,
+        }
+    }
+}
+
+impl CharacterPolicy {
+    pub fn new() -> Self {
+        CharacterPolicy::default()
+    }
+
+    pub fn enable(mut self, class: CharacterClass, enabled: bool) -> Self {
+        self.enabled[class.index()] = enabled;
+        self
+    }
+
+    pub fn minimum(mut self, class: CharacterClass, minimum: u16) -> Self {
+        self.minimums[class.index()] = minimum;
+        self
+    }
+
+    /// Replaces the symbols that can be used, for sites that only accept
+    /// some of them
+    pub fn symbols(mut self, symbols: &str) -> Self {
+        self.symbols.clear();
+        for symbol in symbols.chars() {
+            if !self.symbols.contains(&symbol) {
+                self.symbols.push(symbol);
+            }
+        }
+        self
+    }
+
+    /// Characters that must never appear in generated passwords
+    pub fn exclude(mut self, excluded: &str) -> Self {
+        self.excluded.extend(excluded.chars());
+        self
+    }
+
+    /// Leaves out look-alike characters such as `0`/`O` and `l`/`1`/`I`
+    pub fn avoid_ambiguous(mut self, avoid_ambiguous: bool) -> Self {
+        self.avoid_ambiguous = avoid_ambiguous;
+        self
+    }
+
+    pub fn is_enabled(&self, class: CharacterClass) -> bool {
+        self.enabled[class.index()]
+    }
+
+    /// Minimum number of characters of a class, zero when it is disabled
+    pub fn minimum_of(&self, class: CharacterClass) -> u16 {
+        if self.is_enabled(class) {
+            self.minimums[class.index()]
+        } else {
+            0
+        }
+    }
+
+    /// Shortest password that satisfies every minimum
+    pub fn minimum_length(&self) -> u32 {
+        CharacterClass::ALL
+            .iter()
+            .map(|class| self.minimum_of(*class) as u32)
+            .sum()
+    }
+
+    pub fn enabled_classes(&self) -> Vec<CharacterClass> {
+        CharacterClass::ALL
+            .into_iter()
+            .filter(|class| self.is_enabled(*class))
+            .collect()
+    }
+
+    /// Characters of a class that are allowed by this policy
+    pub fn alphabet(&self, class: CharacterClass) -> Vec<char> {
+        let characters: Vec<char> = match class {
+            CharacterClass::Lowercase => ('a'..='z').collect(),
+            CharacterClass::Uppercase => ('A'..='Z').collect(),
+            CharacterClass::Symbols => self.symbols.clone(),
+            CharacterClass::Digits => ('0'..='9').collect(),
+        };
+        characters
+            .into_iter()
+            .filter(|character| !self.excluded.contains(character))
+            .filter(|character| {
+                !(self.avoid_ambiguous && AMBIGUOUS.contains(*character))
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), BuilderError> {
+        let enabled_classes = self.enabled_classes();
+        if enabled_classes.is_empty() {
+            return Err(BuilderError::NoCharacterClass);
+        }
+        for class in enabled_classes {
+            if self.alphabet(class).is_empty() {
+                return Err(BuilderError::EmptyCharacterClass(class));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusions_and_ambiguous_characters() {
+        let policy =
+            CharacterPolicy::new().exclude("abc").avoid_ambiguous(true);
+        let lowercase = policy.alphabet(CharacterClass::Lowercase);
+        assert!(!lowercase.contains(&'a'));
+        assert!(!lowercase.contains(&'l'));
+        assert!(lowercase.contains(&'d'));
+        assert!(!policy.alphabet(CharacterClass::Digits).contains(&'0'));
+        assert!(!policy.alphabet(CharacterClass::Uppercase).contains(&'O'));
+    }
+
+    #[test]
+    fn custom_symbols() {
+        let policy = CharacterPolicy::new().symbols("!@#");
+        assert_eq!(policy.alphabet(CharacterClass::Symbols), ['!', '@', '#']);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(CharacterPolicy::new().validate().is_ok());
+        let no_classes = CharacterClass::ALL
+            .into_iter()
+            .fold(CharacterPolicy::new(), |policy, class| {
+                policy.enable(class, false)
+            });
+        assert_eq!(no_classes.validate(), Err(BuilderError::NoCharacterClass));
+        assert_eq!(
+            CharacterPolicy::new().exclude("0123456789").validate(),
+            Err(BuilderError::EmptyCharacterClass(CharacterClass::Digits))
+        );
+        assert_eq!(
+            CharacterPolicy::new()
+                .enable(CharacterClass::Digits, false)
+                .exclude("0123456789")
+                .validate(),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use super::password::{Password, MASK};
+use super::version::PasswordVersion;
+
+/// Field whose value differs between two versions. Values missing from
+/// one side were added or removed.
+#[derive(PartialEq, Debug)]
+pub struct FieldChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    /// Whether the values are masked unless revealed
+    pub secret: bool,
+}
+
+/// Line of the notes, as in a line-level diff
+#[derive(PartialEq, Debug)]
+pub enum NoteLine {
+    Kept(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Differences between two versions of a password: the fields that changed
+/// and, when the notes changed, a line diff of them
+#[derive(PartialEq, Debug)]
+pub struct PasswordDiff {
+    name: String,
+    old_version: u32,
+    new_version: u32,
+    fields: Vec<FieldChange>,
+    notes: Vec<NoteLine>,
+    reveal: bool,
+}
+
+impl PasswordDiff {
+    pub fn new(old: &PasswordVersion, new: &PasswordVersion) -> Self {
+        let (old_password, new_password) = (old.password(), new.password());
+        let old_notes = old_password.notes().unwrap_or_default();
+        let new_notes = new_password.notes().unwrap_or_default();
+        let notes = if old_notes == new_notes {
+            Vec::new()
+        } else {
+            diff_lines(old_notes, new_notes)
+        };
+
+        PasswordDiff {
+            name: new_password.name().to_string(),
+            old_version: old.version(),
+            new_version: new.version(),
+            fields: field_changes(old_password, new_password),
+            notes,
+            reveal: false,
+        }
+    }
+
+    /// Shows secret values instead of masking them
+    pub fn reveal(mut self, reveal: bool) -> Self {
+        self.reveal = reveal;
+        self
+    }
+
+    pub fn fields(&self) -> &[FieldChange] {
+        &self.fields
+    }
+
+    pub fn notes(&self) -> &[NoteLine] {
+        &self.notes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.notes.is_empty()
+    }
+}
+
+impl std::fmt::Display for PasswordDiff {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(formatter, "--- {} version {}", self.name, self.old_version)?;
+        write!(
+            formatter,
+            "\n+++ {} version {}",
+            self.name, self.new_version
+        )?;
+        for change in &self.fields {
+            let shown = |value: &str| {
+                if change.secret && !self.reveal {
+                    MASK.to_string()
+                } else {
+                    value.to_string()
+                }
+            };
+            match (&change.old, &change.new) {
+                (Some(old), Some(new)) => write!(
+                    formatter,
+                    "\n~ {}: {} -> {}",
+                    change.name,
+                    shown(old),
+                    shown(new)
+                )?,
+                (Some(old), None) => {
+                    write!(formatter, "\n- {}: {}", change.name, shown(old))?
+                }
+                (None, Some(new)) => {
+                    write!(formatter, "\n+ {}: {}", change.name, shown(new))?
+                }
+                (None, None) => (),
+            }
+        }
+        if !self.notes.is_empty() {
+            write!(formatter, "\nnotes:")?;
+        }
+        for line in &self.notes {
+            match line {
+                NoteLine::Kept(line) => write!(formatter, "\n    {line}")?,
+                NoteLine::Removed(line) => write!(formatter, "\n  - {line}")?,
+                NoteLine::Added(line) => write!(formatter, "\n  + {line}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn field_changes(old: &Password, new: &Password) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let mut compare =
+        |name: &str, old: Option<&str>, new: Option<&str>, secret: bool| {
+            if old != new {
+                changes.push(FieldChange {
+                    name: name.to_string(),
+                    old: old.map(String::from),
+                    new: new.map(String::from),
+                    secret,
+                });
+            }
+        };
+
+    compare("password", Some(old.value()), Some(new.value()), true);
+    compare("username", old.username(), new.username(), false);
+    // URLs are a set, each one is either kept, added or removed
+    for url in old.urls().iter().filter(|url| !new.urls().contains(url)) {
+        compare("url", Some(url), None, false);
+    }
+    for url in new.urls().iter().filter(|url| !old.urls().contains(url)) {
+        compare("url", None, Some(url), false);
+    }
+    for old_field in old.fields() {
+        let new_field = new
+            .fields()
+            .iter()
+            .find(|field| field.name() == old_field.name());
+        compare(
+            old_field.name(),
+            Some(old_field.value()),
+            new_field.map(|field| field.value()),
+            old_field.is_secret() || new_field.is_some_and(|f| f.is_secret()),
+        );
+    }
+    for new_field in new.fields() {
+        if !old
+            .fields()
+            .iter()
+            .any(|field| field.name() == new_field.name())
+        {
+            compare(
+                new_field.name(),
+                None,
+                Some(new_field.value()),
+                new_field.is_secret(),
+            );
+        }
+    }
+    compare("otp", old.otp(), new.otp(), true);
+    changes
+}
+
+/// Line diff from the longest common subsequence of lines
+fn diff_lines(old: &str, new: &str) -> Vec<NoteLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(NoteLine::Kept(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(NoteLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(NoteLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::CustomField;
+
+    fn version(password: Password, version: u32) -> PasswordVersion {
+        PasswordVersion::new(password, version)
+    }
+
+    #[test]
+    fn line_diff() {
+        assert_eq!(
+            diff_lines("a\nb\nc", "a\nc\nd"),
+            [
+                NoteLine::Kept("a".into()),
+                NoteLine::Removed("b".into()),
+                NoteLine::Kept("c".into()),
+                NoteLine::Added("d".into()),
+            ]
+        );
+        assert_eq!(diff_lines("", "new"), [NoteLine::Added("new".into())]);
+    }
+
+    #[test]
+    fn changed_fields_are_masked() {
+        let old = Password::new("bank".into(), "OLD-VALUE".into())
+            .with_username("alice".into())
+            .with_url("https://old.example".into())
+            .with_url("https://bank.example".into())
+            .with_field(CustomField::new("pin".into(), "1234".into(), true))
+            .with_field(CustomField::new("branch".into(), "5".into(), false))
+            .with_notes("Call first\nAsk for Bob".into());
+        let new = Password::new("bank".into(), "NEW-VALUE".into())
+            .with_username("alice".into())
+            .with_url("https://bank.example".into())
+            .with_field(CustomField::new("pin".into(), "9876".into(), true))
+            .with_field(CustomField::new("branch".into(), "5".into(), false))
+            .with_field(CustomField::new("office".into(), "HQ".into(), false))
+            .with_notes("Call first\nAsk for Alice".into());
+
+        let diff = PasswordDiff::new(&version(old, 1), &version(new, 3));
+        assert_eq!(
+            diff.to_string(),
+            "--- bank version 1\n+++ bank version 3\n~ password: ******** -> ********\n- url: https://old.example\n~ pin: ******** -> ********\n+ office: HQ\nnotes:\n    Call first\n  - Ask for Bob\n  + Ask for Alice"
+        );
+        assert!(diff
+            .reveal(true)
+            .to_string()
+            .contains("~ password: OLD-VALUE -> NEW-VALUE\n- url: https://old.example\n~ pin: 1234 -> 9876"));
+    }
+
+    #[test]
+    fn identical_versions() {
+        let password = Password::new("bank".into(), "VALUE".into())
+            .with_otp("JBSWY3DPEHPK3PXP".into());
+        let diff = PasswordDiff::new(
+            &version(password.clone(), 1),
+            &version(password, 2),
+        );
+        assert!(diff.is_empty());
+    }
+}
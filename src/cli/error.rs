@@ -0,0 +1,103 @@
+use std::io;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// A password with this name already exists
+    AlreadyExists(String),
+    /// There is no password with this name
+    NotFound(String),
+    /// The password exists but doesn't have the requested version
+    VersionNotFound {
+        name: String,
+        version: u32,
+    },
+    /// The name is not a valid relative path inside the repository
+    InvalidName {
+        name: String,
+        reason: &'static str,
+    },
+    /// The repository root has no vault header
+    NotInitialized,
+    /// The repository root already has a vault header
+    AlreadyInitialized,
+    /// The repository root holds this many passwords written before
+    /// versions were encrypted, which a new vault header would make
+    /// unreadable
+    Unencrypted(usize),
+    /// The repository has not been unlocked with the master password
+    Locked,
+    /// The master password does not match the one the vault was created with
+    WrongMasterPassword,
+    /// The repository root can be read or changed by other users
+    Insecure(String),
+    /// Another process kept the repository locked for longer than the
+    /// timeout
+    Busy(Duration),
+    /// Stored data could not be decoded or failed authentication
+    Corrupt(String),
+    Io(io::Error),
+}
+
+impl std::fmt::Display for RepositoryError {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            RepositoryError::AlreadyExists(name) => {
+                write!(formatter, "password {name} already exists")
+            }
+            RepositoryError::NotFound(name) => {
+                write!(formatter, "password {name} not found")
+            }
+            RepositoryError::VersionNotFound { name, version } => {
+                write!(formatter, "password {name} has no version {version}")
+            }
+            RepositoryError::InvalidName { name, reason } => {
+                write!(formatter, "invalid password name '{name}': {reason}")
+            }
+            RepositoryError::NotInitialized => {
+                write!(formatter, "password manager is not initialized")
+            }
+            RepositoryError::AlreadyInitialized => {
+                write!(formatter, "password manager is already initialized")
+            }
+            RepositoryError::Unencrypted(count) => write!(
+                formatter,
+                "password store holds {count} unencrypted passwords"
+            ),
+            RepositoryError::Locked => write!(formatter, "vault is locked"),
+            RepositoryError::WrongMasterPassword => {
+                write!(formatter, "wrong master password")
+            }
+            RepositoryError::Insecure(reason) => {
+                write!(formatter, "password store is exposed: {reason}")
+            }
+            RepositoryError::Busy(timeout) => write!(
+                formatter,
+                "password store is busy, gave up waiting after {}s",
+                timeout.as_secs_f64()
+            ),
+            RepositoryError::Corrupt(reason) => {
+                write!(formatter, "stored data is corrupt: {reason}")
+            }
+            RepositoryError::Io(error) => write!(formatter, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepositoryError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RepositoryError {
+    fn from(error: io::Error) -> Self {
+        RepositoryError::Io(error)
+    }
+}
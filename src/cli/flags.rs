@@ -1,15 +1,201 @@
-pub struct GetFlags {
-    pub version: Option<u32>,
+use super::password::{CustomField, Password};
+use super::storage::StorageFormat;
+use std::path::PathBuf;
+
+#[derive(Default)]
+pub struct InitFlags {
+    pub format: StorageFormat,
 }
 
-impl Default for GetFlags {
-    fn default() -> Self {
-        GetFlags { version: None }
+impl InitFlags {
+    pub fn new() -> Self {
+        InitFlags::default()
     }
 }
 
+#[derive(Default)]
+pub struct GetFlags {
+    pub version: Option<u32>,
+    /// Print only this field instead of the whole entry
+    pub field: Option<String>,
+}
+
 impl GetFlags {
     pub fn new() -> Self {
         GetFlags::default()
     }
 }
+
+#[derive(Default)]
+pub struct AuditFlags {
+    /// Have I Been Pwned hash file or range directory to check against
+    pub breaches: Option<PathBuf>,
+    /// Check old versions too, not only the latest ones
+    pub all_versions: bool,
+    /// Report identical and similar values
+    pub reuse: bool,
+    pub json: bool,
+}
+
+impl AuditFlags {
+    pub fn new() -> Self {
+        AuditFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct ListFlags {
+    /// Only list the passwords in this folder
+    pub prefix: Option<String>,
+    /// One full name per line instead of a tree
+    pub flat: bool,
+}
+
+impl ListFlags {
+    pub fn new() -> Self {
+        ListFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct MoveFlags {
+    /// Replace the destination if it already exists
+    pub force: bool,
+}
+
+impl MoveFlags {
+    pub fn new() -> Self {
+        MoveFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct RemoveFlags {
+    /// Delete the versions instead of moving them to the trash
+    pub permanent: bool,
+}
+
+impl RemoveFlags {
+    pub fn new() -> Self {
+        RemoveFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct EmptyTrashFlags {
+    /// Only delete passwords removed at least these seconds ago
+    pub older_than: Option<u64>,
+}
+
+impl EmptyTrashFlags {
+    pub fn new() -> Self {
+        EmptyTrashFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct RollbackFlags {
+    pub message: Option<String>,
+}
+
+impl RollbackFlags {
+    pub fn new() -> Self {
+        RollbackFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct PruneFlags {
+    /// Only prune this password instead of every one
+    pub name: Option<String>,
+    pub keep_last: Option<u32>,
+    /// Seconds
+    pub keep_newer_than: Option<u64>,
+    /// List what would be removed without removing it
+    pub dry_run: bool,
+}
+
+impl PruneFlags {
+    pub fn new() -> Self {
+        PruneFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct DiffFlags {
+    /// Versions to compare, the older one first
+    pub versions: Vec<u32>,
+    /// Show secret values instead of masking them
+    pub reveal: bool,
+}
+
+impl DiffFlags {
+    pub fn new() -> Self {
+        DiffFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct PolicyFlags {
+    /// Name prefix the policy applies to, every password if missing
+    pub prefix: Option<String>,
+    pub max_age: Option<u32>,
+    pub clear: bool,
+}
+
+impl PolicyFlags {
+    pub fn new() -> Self {
+        PolicyFlags::default()
+    }
+}
+
+#[derive(Default)]
+pub struct WriteFlags {
+    /// Value given on the command line instead of generating one
+    pub value: Option<String>,
+    pub message: Option<String>,
+    pub username: Option<String>,
+    pub urls: Vec<String>,
+    pub notes: Option<String>,
+    pub fields: Vec<CustomField>,
+    pub otp: Option<String>,
+    /// Refuse weak values instead of only warning about them
+    pub strict: bool,
+    /// Whether any flag configuring the generator was given
+    pub generate: bool,
+}
+
+impl WriteFlags {
+    pub fn new() -> Self {
+        WriteFlags::default()
+    }
+
+    /// Whether any entry field besides the value is given
+    pub fn sets_fields(&self) -> bool {
+        self.username.is_some()
+            || !self.urls.is_empty()
+            || self.notes.is_some()
+            || !self.fields.is_empty()
+            || self.otp.is_some()
+    }
+
+    /// Sets every entry field given on the command line, keeping the rest
+    pub fn apply(self, mut password: Password) -> Password {
+        if let Some(username) = self.username {
+            password = password.with_username(username);
+        }
+        for url in self.urls {
+            password = password.with_url(url);
+        }
+        if let Some(notes) = self.notes {
+            password = password.with_notes(notes);
+        }
+        for field in self.fields {
+            password = password.with_field(field);
+        }
+        if let Some(otp) = self.otp {
+            password = password.with_otp(otp);
+        }
+        password
+    }
+}
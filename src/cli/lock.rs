@@ -0,0 +1,191 @@
+use super::error::RepositoryError;
+use super::permissions;
+use std::cell::RefCell;
+use std::fs::{File, TryLockError};
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// File in the repository root that processes lock before touching any
+/// password
+pub const LOCK_FILE: &str = ".lock";
+
+/// How long to wait for other processes before giving up
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether the lock was released
+const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Advisory lock on a whole repository, shared by any number of readers or
+/// held by a single writer. Locking is reentrant, so operations built on
+/// top of others only lock the file once, but a shared lock can't be
+/// upgraded to an exclusive one.
+pub struct RepositoryLock {
+    /// Lock file, missing for repositories that only live in this process
+    path: Option<PathBuf>,
+    timeout: Duration,
+    held: RefCell<HeldLock>,
+}
+
+#[derive(Default)]
+struct HeldLock {
+    file: Option<File>,
+    exclusive: bool,
+    depth: usize,
+}
+
+/// Releases its share of the lock when dropped
+pub struct LockGuard<'a> {
+    lock: &'a RepositoryLock,
+}
+
+impl RepositoryLock {
+    pub fn new(path: PathBuf) -> Self {
+        RepositoryLock {
+            path: Some(path),
+            timeout: DEFAULT_TIMEOUT,
+            held: RefCell::default(),
+        }
+    }
+
+    /// Lock of a repository that other processes can't reach, which never
+    /// has to wait
+    pub fn in_process() -> Self {
+        RepositoryLock {
+            path: None,
+            timeout: DEFAULT_TIMEOUT,
+            held: RefCell::default(),
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits until no process is writing
+    pub fn shared(&self) -> Result<LockGuard<'_>, RepositoryError> {
+        self.acquire(false)
+    }
+
+    /// Waits until no other process is reading or writing
+    pub fn exclusive(&self) -> Result<LockGuard<'_>, RepositoryError> {
+        self.acquire(true)
+    }
+
+    fn acquire(
+        &self,
+        exclusive: bool,
+    ) -> Result<LockGuard<'_>, RepositoryError> {
+        let mut held = self.held.borrow_mut();
+        if held.depth == 0 {
+            held.file = self.lock_file(exclusive)?;
+            held.exclusive = exclusive;
+        } else {
+            assert!(
+                held.exclusive || !exclusive,
+                "a shared repository lock can't be upgraded"
+            );
+        }
+        held.depth += 1;
+        Ok(LockGuard { lock: self })
+    }
+
+    fn lock_file(
+        &self,
+        exclusive: bool,
+    ) -> Result<Option<File>, RepositoryError> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let file = match permissions::file_options().open(path) {
+            Ok(file) => file,
+            // There is nothing to protect before the repository root exists
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let result = if exclusive {
+                file.try_lock()
+            } else {
+                file.try_lock_shared()
+            };
+            match result {
+                Ok(()) => return Ok(Some(file)),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(RETRY_INTERVAL)
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(RepositoryError::Busy(self.timeout))
+                }
+                Err(TryLockError::Error(error)) => return Err(error.into()),
+            }
+        }
+    }
+}
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        let mut held = self.lock.held.borrow_mut();
+        held.depth -= 1;
+        if held.depth == 0 {
+            // Closing the file releases the lock
+            held.file = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn readers_share_and_writers_wait() {
+        let root = tempfile::tempdir().unwrap();
+        let lock = || {
+            RepositoryLock::new(root.path().join(LOCK_FILE)).timeout(TIMEOUT)
+        };
+        let (first, second) = (lock(), lock());
+
+        let reading = first.shared().unwrap();
+        second.shared().unwrap();
+        assert!(matches!(second.exclusive(), Err(RepositoryError::Busy(_))));
+        drop(reading);
+
+        let writing = first.exclusive().unwrap();
+        assert!(matches!(second.shared(), Err(RepositoryError::Busy(_))));
+        drop(writing);
+        second.exclusive().unwrap();
+    }
+
+    #[test]
+    fn nested_locks_are_released_last() {
+        let root = tempfile::tempdir().unwrap();
+        let lock = || {
+            RepositoryLock::new(root.path().join(LOCK_FILE)).timeout(TIMEOUT)
+        };
+        let (first, second) = (lock(), lock());
+
+        let writing = first.exclusive().unwrap();
+        let reading = first.shared().unwrap();
+        drop(writing);
+        assert!(matches!(second.shared(), Err(RepositoryError::Busy(_))));
+        drop(reading);
+        second.exclusive().unwrap();
+    }
+
+    #[test]
+    fn missing_roots_are_not_locked() {
+        let root = tempfile::tempdir().unwrap();
+        let lock = RepositoryLock::new(root.path().join("missing/.lock"));
+        lock.exclusive().unwrap();
+        assert!(!root.path().join("missing").exists());
+    }
+}
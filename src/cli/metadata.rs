@@ -0,0 +1,270 @@
+use super::rotation::SECONDS_PER_DAY;
+use super::PasswordBuilder;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_HOUR: u64 = 3_600;
+
+/// Information about who wrote a password version, when and why. It is
+/// stored next to every version as an encrypted sidecar file.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct VersionMetadata {
+    /// Seconds since the Unix epoch
+    created_at: u64,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+    /// Settings the value was generated with, absent for values given by
+    /// the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    generator: Option<PasswordBuilder>,
+    /// Version whose content this one restored, for rollbacks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rollback_of: Option<u32>,
+}
+
+impl Default for VersionMetadata {
+    fn default() -> Self {
+        let created_at = unix_now();
+        let user = ["USER", "USERNAME", "LOGNAME"]
+            .iter()
+            .find_map(|variable| std::env::var(variable).ok())
+            .or_else(account_name);
+        let hostname = hostname::get()
+            .ok()
+            .and_then(|hostname| hostname.into_string().ok());
+
+        VersionMetadata {
+            created_at,
+            user,
+            hostname,
+            message: None,
+            generator: None,
+            rollback_of: None,
+        }
+    }
+}
+
+impl VersionMetadata {
+    /// Metadata for a version written right now by the current user
+    pub fn new() -> Self {
+        VersionMetadata::default()
+    }
+
+    pub fn with_message(mut self, message: Option<String>) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn with_generator(
+        mut self,
+        generator: Option<PasswordBuilder>,
+    ) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    pub fn with_rollback_of(mut self, version: Option<u32>) -> Self {
+        self.rollback_of = version;
+        self
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    pub fn generator(&self) -> Option<&PasswordBuilder> {
+        self.generator.as_ref()
+    }
+
+    pub fn rollback_of(&self) -> Option<u32> {
+        self.rollback_of
+    }
+}
+
+impl std::fmt::Display for VersionMetadata {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            formatter,
+            "{}  {}@{}",
+            format_timestamp(self.created_at),
+            self.user().unwrap_or("unknown"),
+            self.hostname().unwrap_or("unknown"),
+        )?;
+        if let Some(version) = self.rollback_of {
+            write!(formatter, "  (rollback to version {version})")?;
+        }
+        if let Some(message) = self.message() {
+            write!(formatter, "  {message}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the account running the process, for when cron, systemd or
+/// `env -i` leave out the variables naming the user
+fn account_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        // SAFETY: passwd only holds integers and pointers, for which zero
+        // is a valid value
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buffer = vec![0; 4096];
+        let mut found = std::ptr::null_mut();
+        // SAFETY: every pointer is valid for the duration of the call and
+        // the buffer length is the one it was allocated with
+        let status = unsafe {
+            libc::getpwuid_r(
+                libc::geteuid(),
+                &mut entry,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut found,
+            )
+        };
+        if status != 0 || found.is_null() || entry.pw_name.is_null() {
+            return None;
+        }
+        // SAFETY: on success the name is a NUL terminated string inside
+        // the buffer, which is still alive
+        let name = unsafe { std::ffi::CStr::from_ptr(entry.pw_name) };
+        name.to_str().ok().map(str::to_string)
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parses an age such as `30d`, `12h` or `2w` into seconds. Numbers
+/// without a unit are days.
+pub fn parse_age(age: &str) -> Option<u64> {
+    let (number, unit) = match age.char_indices().last()? {
+        (index, unit) if unit.is_ascii_alphabetic() => (&age[..index], unit),
+        _ => (age, 'd'),
+    };
+    let seconds = match unit {
+        'h' => SECONDS_PER_HOUR,
+        'd' => SECONDS_PER_DAY,
+        'w' => 7 * SECONDS_PER_DAY,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(seconds)
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS UTC`
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let seconds = timestamp % 86_400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Converts days since the Unix epoch to a proleptic Gregorian date, see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_792_321_199), "2026-10-18 10:59:59 UTC");
+    }
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("30d"), Some(30 * SECONDS_PER_DAY));
+        assert_eq!(parse_age("30"), Some(30 * SECONDS_PER_DAY));
+        assert_eq!(parse_age("12h"), Some(12 * SECONDS_PER_HOUR));
+        assert_eq!(parse_age("2w"), Some(14 * SECONDS_PER_DAY));
+        assert_eq!(parse_age("0d"), Some(0));
+        for age in ["", "d", "30m", "-1d", "1.5d", "99999999999999999999d"] {
+            assert_eq!(parse_age(age), None, "{age}");
+        }
+    }
+
+    #[test]
+    fn metadata_roundtrip() {
+        let metadata =
+            VersionMetadata::new().with_message(Some("Rotated".to_string()));
+        let serialized = serde_json::to_string(&metadata).unwrap();
+        let deserialized: VersionMetadata =
+            serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, metadata);
+        assert_eq!(deserialized.message(), Some("Rotated"));
+        assert!(!serialized.contains("rollback_of"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn account_names() {
+        assert!(account_name().is_some_and(|name| !name.is_empty()));
+    }
+
+    #[test]
+    fn rollback_metadata() {
+        let metadata = VersionMetadata::new()
+            .with_rollback_of(Some(2))
+            .with_message(Some("Old one worked".to_string()));
+        let serialized = serde_json::to_string(&metadata).unwrap();
+        let deserialized: VersionMetadata =
+            serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.rollback_of(), Some(2));
+        assert!(metadata
+            .to_string()
+            .ends_with("  (rollback to version 2)  Old one worked"));
+    }
+}
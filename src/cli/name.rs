@@ -0,0 +1,251 @@
+use super::error::RepositoryError;
+use super::storage::VAULT_FILE;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Separator between the folders of a hierarchical name such as
+/// `work/aws/prod`
+pub const SEPARATOR: char = '/';
+
+/// Device names that can't be used as file names on Windows, with or
+/// without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5",
+    "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5",
+    "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Name of a password, checked to be a relative path of plain segments so
+/// that it can never point outside of the repository or clash with the
+/// files of another password
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PasswordName(String);
+
+impl PasswordName {
+    pub fn new<S: Into<String>>(name: S) -> Result<Self, RepositoryError> {
+        let name = name.into();
+        match validate(&name) {
+            Ok(()) => Ok(PasswordName(name)),
+            Err(reason) => Err(RepositoryError::InvalidName { name, reason }),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Name of a password nested in this one
+    pub fn join(&self, segment: &str) -> Result<Self, RepositoryError> {
+        PasswordName::new(format!("{}{SEPARATOR}{segment}", self.0))
+    }
+
+    /// Path of the password folder relative to the repository root
+    pub fn to_path(&self) -> PathBuf {
+        self.0.split(SEPARATOR).collect()
+    }
+}
+
+impl std::fmt::Display for PasswordName {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(formatter, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for PasswordName {
+    type Err = RepositoryError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        PasswordName::new(name)
+    }
+}
+
+impl AsRef<str> for PasswordName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn validate(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("it is empty");
+    }
+    if name.starts_with(SEPARATOR) {
+        return Err("it is an absolute path");
+    }
+    if name.chars().any(char::is_control) {
+        return Err("it contains control characters");
+    }
+    if name.contains(['\\', ':']) {
+        return Err("it contains a backslash or a colon");
+    }
+    for (index, segment) in name.split(SEPARATOR).enumerate() {
+        if segment.is_empty() {
+            return Err("it has an empty segment");
+        }
+        if segment == "." || segment == ".." {
+            return Err("it has a `.` or `..` segment");
+        }
+        if segment.starts_with('.') {
+            return Err("a segment starts with `.`");
+        }
+        if segment.ends_with([' ', '.']) {
+            return Err("a segment ends with a space or a `.`");
+        }
+        let stem = segment.split('.').next().unwrap_or(segment);
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+        {
+            return Err("a segment is a reserved device name");
+        }
+        // The folder of a top-level password would be taken for a vault
+        if index == 0 && segment.eq_ignore_ascii_case(VAULT_FILE) {
+            return Err("it is the name of the vault file");
+        }
+        // Nested segments live next to the version files of their parent
+        if index > 0 && is_version_file(segment) {
+            return Err("a nested segment looks like a version number");
+        }
+    }
+    Ok(())
+}
+
+/// Folder of a tree of names
+#[derive(Default)]
+struct Folder<'a> {
+    is_password: bool,
+    children: BTreeMap<&'a str, Folder<'a>>,
+}
+
+/// Draws the names as a tree of folders. Folders that are not passwords
+/// themselves end with the separator.
+pub fn tree(names: &[PasswordName]) -> String {
+    let mut root = Folder::default();
+    for name in names {
+        let mut folder = &mut root;
+        for segment in name.as_str().split(SEPARATOR) {
+            folder = folder.children.entry(segment).or_default();
+        }
+        folder.is_password = true;
+    }
+
+    let mut lines = Vec::new();
+    for (segment, folder) in &root.children {
+        lines.push(label(segment, folder));
+        draw_children(folder, "", &mut lines);
+    }
+    lines.join("\n")
+}
+
+fn draw_children(folder: &Folder, indent: &str, lines: &mut Vec<String>) {
+    let last = folder.children.len().saturating_sub(1);
+    for (index, (segment, child)) in folder.children.iter().enumerate() {
+        let (branch, continuation) = if index == last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        lines.push(format!("{indent}{branch}{}", label(segment, child)));
+        draw_children(child, &format!("{indent}{continuation}"), lines);
+    }
+}
+
+fn label(segment: &str, folder: &Folder) -> String {
+    if folder.is_password {
+        segment.to_string()
+    } else {
+        format!("{segment}{SEPARATOR}")
+    }
+}
+
+fn is_version_file(segment: &str) -> bool {
+    let version = segment
+        .split_once('.')
+        .map_or(segment, |(version, _)| version);
+    !version.is_empty() && version.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_names() {
+        for name in [
+            "bank",
+            "work/aws/prod",
+            "2024",
+            "my.site/login",
+            "console",
+            "work/passwords.vault",
+        ] {
+            let parsed = PasswordName::new(name).unwrap();
+            assert_eq!(parsed.as_str(), name);
+        }
+    }
+
+    #[test]
+    fn invalid_names() {
+        for name in [
+            "",
+            "/etc/passwd",
+            "../outside",
+            "work/../../outside",
+            "work/./aws",
+            "work//aws",
+            "work/",
+            ".vault",
+            "work/.hidden",
+            "work\\aws",
+            "work/1",
+            "work/1.meta",
+            "C:/Windows",
+            "line\nbreak",
+            "tab\tname",
+            "nul\0byte",
+            "escape\u{1b}[2J",
+            "trailing.",
+            "work/trailing ",
+            "CON",
+            "work/nul.txt",
+            "Lpt1",
+            "passwords.vault",
+            "Passwords.Vault/bank",
+        ] {
+            assert!(
+                matches!(
+                    PasswordName::new(name),
+                    Err(RepositoryError::InvalidName { .. })
+                ),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn nested_names() {
+        let work = PasswordName::new("work").unwrap();
+        assert_eq!(work.join("aws").unwrap().as_str(), "work/aws");
+        assert!(work.join("..").is_err());
+        assert!(work.join("1").is_err());
+        assert_eq!(
+            work.join("aws").unwrap().to_path(),
+            PathBuf::from("work").join("aws")
+        );
+    }
+
+    #[test]
+    fn tree_of_names() {
+        let names: Vec<PasswordName> =
+            ["bank", "work", "work/aws/dev", "work/aws/prod", "work/vpn"]
+                .map(|name| name.parse().unwrap())
+                .to_vec();
+        assert_eq!(
+            tree(&names),
+            "bank\nwork\n├── aws/\n│   ├── dev\n│   └── prod\n└── vpn"
+        );
+    }
+}
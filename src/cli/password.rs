@@ -1,9 +1,64 @@
+use super::error::RepositoryError;
+use super::permissions;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::path::PathBuf;
 
-#[derive(PartialEq, Debug, Clone)]
+/// Marks stored versions holding a full entry. Versions written before
+/// entries had fields hold the bare password value instead.
+const ENTRY_PREFIX: &[u8] = b"pwm-entry-v1\n";
+
+/// Text shown in place of secret values
+pub(crate) const MASK: &str = "********";
+
+/// Arbitrary key/value pair attached to a password
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CustomField {
+    name: String,
+    value: String,
+    #[serde(default)]
+    secret: bool,
+}
+
+impl CustomField {
+    pub fn new(name: String, value: String, secret: bool) -> Self {
+        CustomField {
+            name,
+            value,
+            secret,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.secret
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Password {
+    /// Names are given by where the entry is stored, not by its contents
+    #[serde(skip)]
     name: String,
     value: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<CustomField>,
+    /// Base32 secret or `otpauth://` URI of the one-time password generator
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    otp: Option<String>,
 }
 
 impl std::fmt::Display for Password {
@@ -11,13 +66,41 @@ impl std::fmt::Display for Password {
         &self,
         formatter: &mut std::fmt::Formatter<'_>,
     ) -> std::fmt::Result {
-        write!(formatter, "{}: {}", self.name, self.value)
+        write!(formatter, "{}: {}", self.name, self.value)?;
+        if let Some(username) = &self.username {
+            write!(formatter, "\nusername: {username}")?;
+        }
+        for url in &self.urls {
+            write!(formatter, "\nurl: {url}")?;
+        }
+        for field in &self.fields {
+            let value = if field.secret { MASK } else { &field.value };
+            write!(formatter, "\n{}: {}", field.name, value)?;
+        }
+        if self.otp.is_some() {
+            write!(formatter, "\notp: {MASK}")?;
+        }
+        if let Some(notes) = &self.notes {
+            write!(formatter, "\nnotes:")?;
+            for line in notes.lines() {
+                write!(formatter, "\n  {line}")?;
+            }
+        }
+        Ok(())
     }
 }
 
 impl Password {
     pub fn new(name: String, value: String) -> Self {
-        Password { name, value }
+        Password {
+            name,
+            value,
+            username: None,
+            urls: Vec::new(),
+            notes: None,
+            fields: Vec::new(),
+            otp: None,
+        }
     }
 
     pub fn name(&self) -> &str {
@@ -27,8 +110,127 @@ impl Password {
     pub fn value(&self) -> &str {
         &self.value
     }
-}
 
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn urls(&self) -> &[String] {
+        &self.urls
+    }
+
+    pub fn notes(&self) -> Option<&str> {
+        self.notes.as_deref()
+    }
+
+    pub fn fields(&self) -> &[CustomField] {
+        &self.fields
+    }
+
+    pub fn otp(&self) -> Option<&str> {
+        self.otp.as_deref()
+    }
+
+    /// Looks up a field by name. Besides custom fields, `value` (or
+    /// `password`), `username`, `url`, `notes` and `otp` are recognized.
+    /// Only several URLs, joined one per line, are not borrowed.
+    pub fn field(&self, field_name: &str) -> Option<Cow<'_, str>> {
+        match field_name {
+            "value" | "password" => Some(Cow::Borrowed(&self.value)),
+            "username" => self.username().map(Cow::Borrowed),
+            "url" | "urls" => match self.urls.as_slice() {
+                [] => None,
+                [url] => Some(Cow::Borrowed(url)),
+                urls => Some(Cow::Owned(urls.join("\n"))),
+            },
+            "notes" => self.notes().map(Cow::Borrowed),
+            "otp" => self.otp().map(Cow::Borrowed),
+            _ => self
+                .fields
+                .iter()
+                .find(|field| field.name == field_name)
+                .map(|field| Cow::Borrowed(field.value.as_str())),
+        }
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_value(mut self, value: String) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn with_username(mut self, username: String) -> Self {
+        self.username = Some(username);
+        self
+    }
+
+    pub fn with_url(mut self, url: String) -> Self {
+        if !self.urls.contains(&url) {
+            self.urls.push(url);
+        }
+        self
+    }
+
+    pub fn with_notes(mut self, notes: String) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+
+    pub fn with_otp(mut self, otp: String) -> Self {
+        self.otp = Some(otp);
+        self
+    }
+
+    /// Adds a custom field, replacing any other field with the same name
+    pub fn with_field(mut self, field: CustomField) -> Self {
+        match self
+            .fields
+            .iter_mut()
+            .find(|existing| existing.name == field.name)
+        {
+            Some(existing) => *existing = field,
+            None => self.fields.push(field),
+        }
+        self
+    }
+
+    /// Encodes everything but the name, which is given by the storage
+    /// location
+    pub(crate) fn to_record(&self) -> Vec<u8> {
+        let mut record = ENTRY_PREFIX.to_vec();
+        record.extend(
+            serde_json::to_vec(self)
+                .expect("Serializing a password to JSON cannot fail"),
+        );
+        record
+    }
+
+    pub(crate) fn from_record(
+        name: String,
+        record: Vec<u8>,
+    ) -> Result<Password, RepositoryError> {
+        let password = match record.strip_prefix(ENTRY_PREFIX) {
+            Some(entry) => {
+                serde_json::from_slice::<Password>(entry).map_err(|error| {
+                    RepositoryError::Corrupt(format!("{name}: {error}"))
+                })?
+            }
+            None => {
+                let value = String::from_utf8(record).map_err(|_| {
+                    RepositoryError::Corrupt(format!(
+                        "{name} is not valid UTF-8"
+                    ))
+                })?;
+                Password::new(String::new(), value)
+            }
+        };
+        Ok(password.with_name(name))
+    }
+}
 
 // TODO: Rethink this
 impl Password {
@@ -37,9 +239,7 @@ impl Password {
             Ok(home_path) => PathBuf::from(&home_path),
             Err(_) => {
                 let home_string = std::env::var("HOME").unwrap();
-                let home_path =
-                    PathBuf::from_iter([&home_string, ".password"]);
-                home_path
+                PathBuf::from_iter([&home_string, ".password"])
             }
         }
     }
@@ -47,7 +247,64 @@ impl Password {
     pub fn create_home_directory() {
         let home_path = Password::default_path();
         if !home_path.exists() {
-            std::fs::create_dir(&home_path).unwrap();
+            permissions::create_dir(&home_path).unwrap();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_roundtrip() {
+        let password =
+            Password::new("bank".to_string(), "hunter2".to_string())
+                .with_username("alice".to_string())
+                .with_url("https://bank.example".to_string())
+                .with_notes("Security questions\nPet: Rex".to_string())
+                .with_field(CustomField::new(
+                    "pin".to_string(),
+                    "1234".to_string(),
+                    true,
+                ));
+
+        let restored =
+            Password::from_record("bank".to_string(), password.to_record())
+                .unwrap();
+        assert_eq!(restored, password);
+        assert_eq!(restored.field("username").as_deref(), Some("alice"));
+        assert_eq!(restored.field("pin").as_deref(), Some("1234"));
+        assert_eq!(restored.field("missing"), None);
+    }
+
+    #[test]
+    fn legacy_records_hold_only_the_value() {
+        let restored =
+            Password::from_record("old".to_string(), b"hunter2".to_vec())
+                .unwrap();
+        assert_eq!(
+            restored,
+            Password::new("old".to_string(), "hunter2".to_string())
+        );
+    }
+
+    #[test]
+    fn secret_fields_are_masked() {
+        let password = Password::new("api".to_string(), "value".to_string())
+            .with_field(CustomField::new(
+                "token".to_string(),
+                "abc".to_string(),
+                true,
+            ))
+            .with_field(CustomField::new(
+                "region".to_string(),
+                "eu".to_string(),
+                false,
+            ));
+        assert_eq!(
+            password.to_string(),
+            "api: value\ntoken: ********\nregion: eu"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io;
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::fs::{
+    DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt,
+};
+
+/// Only the owner can list, read or write folders of the store
+pub const FOLDER_MODE: u32 = 0o700;
+
+/// Only the owner can read or write files of the store
+pub const FILE_MODE: u32 = 0o600;
+
+/// Creates a folder, and the missing ones above it, only accessible by
+/// the current user
+pub fn create_dir_all(path: &Path) -> io::Result<()> {
+    folder_builder().recursive(true).create(path)
+}
+
+/// Creates a folder only accessible by the current user
+pub fn create_dir(path: &Path) -> io::Result<()> {
+    folder_builder().create(path)
+}
+
+/// Options to create a new file only accessible by the current user
+pub fn new_file_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(FILE_MODE);
+    options
+}
+
+/// Options to open a file for reading and writing, creating it only
+/// accessible by the current user if it is missing
+pub fn file_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    options.mode(FILE_MODE);
+    options
+}
+
+/// Restricts an existing file to the current user, for copies that would
+/// keep the permissions of their source
+pub fn restrict_file(path: &Path) -> io::Result<()> {
+    restrict(path, FILE_MODE)
+}
+
+/// Restricts an existing folder to the current user
+pub fn restrict_folder(path: &Path) -> io::Result<()> {
+    restrict(path, FOLDER_MODE)
+}
+
+/// Reason why the store root can be read or changed by other users, if it
+/// can. Only checked on Unix, where ownership and modes are known.
+pub fn exposure(root: &Path) -> io::Result<Option<String>> {
+    #[cfg(unix)]
+    {
+        let metadata = fs::metadata(root)?;
+        // SAFETY: geteuid has no preconditions and never fails
+        let user = unsafe { libc::geteuid() };
+        if metadata.uid() != user {
+            return Ok(Some(format!(
+                "{} is owned by user {} instead of {user}",
+                root.display(),
+                metadata.uid()
+            )));
+        }
+        let mode = metadata.mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Ok(Some(format!(
+                "{} can be accessed by other users (mode {mode:03o})",
+                root.display()
+            )));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = root;
+    Ok(None)
+}
+
+fn restrict(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+fn folder_builder() -> DirBuilder {
+    let mut builder = DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode(FOLDER_MODE);
+    builder
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().mode() & 0o777
+    }
+
+    #[test]
+    fn private_folders_and_files() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("work/aws");
+        create_dir_all(&nested).unwrap();
+        assert_eq!(mode(&root.path().join("work")), FOLDER_MODE);
+        assert_eq!(mode(&nested), FOLDER_MODE);
+
+        let file = nested.join("1");
+        new_file_options().open(&file).unwrap();
+        assert_eq!(mode(&file), FILE_MODE);
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        restrict_file(&file).unwrap();
+        assert_eq!(mode(&file), FILE_MODE);
+    }
+
+    #[test]
+    fn exposed_roots() {
+        let root = tempfile::tempdir().unwrap();
+        fs::set_permissions(root.path(), fs::Permissions::from_mode(0o700))
+            .unwrap();
+        assert_eq!(exposure(root.path()).unwrap(), None);
+
+        fs::set_permissions(root.path(), fs::Permissions::from_mode(0o750))
+            .unwrap();
+        let reason = exposure(root.path()).unwrap().unwrap();
+        assert!(reason.ends_with("can be accessed by other users (mode 750)"));
+    }
+}
@@ -1,12 +1,36 @@
+use super::error::RepositoryError;
 use super::flags::GetFlags;
+use super::lock::{RepositoryLock, LOCK_FILE};
+use super::metadata::{unix_now, VersionMetadata};
+use super::name::{PasswordName, SEPARATOR};
+use super::otp;
 use super::password::Password;
+use super::permissions;
+use super::retention::Retention;
+use super::rotation::RotationPolicy;
+use super::storage::{Storage, StorageFormat, StoredVersion};
+use super::trash::TrashEntry;
+use super::vault::{AssociatedData, VaultHeader, VaultKey};
 use super::version::PasswordVersion;
-use std::io::Write;
-use std::path::{Path, PathBuf};
-use std::{self, fs, io};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Setting holding the encrypted rotation policy
+const ROTATION_POLICY_SETTING: &str = "rotation";
 
 pub struct PasswordRepository {
-    root_dir: PathBuf,
+    /// Folder holding the storage, missing for storages that are not on
+    /// the file system
+    root_dir: Option<PathBuf>,
+    storage: Box<dyn Storage>,
+    key: Option<Rc<VaultKey>>,
+    /// Open the repository even if other users can access its root
+    allow_insecure: bool,
+    /// Keeps concurrent processes from reading or writing half-done changes
+    lock: RepositoryLock,
 }
 
 impl Default for PasswordRepository {
@@ -15,7 +39,7 @@ impl Default for PasswordRepository {
             Ok(path) => PathBuf::from(&path),
             Err(_) => Password::default_path(),
         };
-        PasswordRepository { root_dir }
+        PasswordRepository::with_root_dir(root_dir)
     }
 }
 
@@ -24,140 +48,715 @@ impl PasswordRepository {
         PasswordRepository::default()
     }
 
-    pub fn add(&self, password: &Password) {
-        let password_folder = self.root_dir.join(Path::new(password.name()));
+    /// Repository in `root_dir`, in the format it was created with or the
+    /// directory format if it doesn't exist yet
+    pub fn with_root_dir<P: Into<PathBuf>>(root_dir: P) -> Self {
+        let root_dir = root_dir.into();
+        PasswordRepository {
+            lock: RepositoryLock::new(root_dir.join(LOCK_FILE)),
+            storage: StorageFormat::detect(&root_dir).storage(&root_dir),
+            root_dir: Some(root_dir),
+            key: None,
+            allow_insecure: false,
+        }
+    }
+
+    /// Repository kept in any storage, such as a [`MemoryStorage`] or one
+    /// implemented outside of this crate. It is only locked against other
+    /// users within this process.
+    ///
+    /// [`MemoryStorage`]: super::MemoryStorage
+    pub fn with_storage<S: Storage + 'static>(storage: S) -> Self {
+        PasswordRepository {
+            root_dir: None,
+            storage: Box::new(storage),
+            key: None,
+            allow_insecure: false,
+            lock: RepositoryLock::in_process(),
+        }
+    }
+
+    /// Format used by `init` to create a repository in a root folder.
+    /// Repositories that already exist keep their own.
+    pub fn storage_format(mut self, format: StorageFormat) -> Self {
+        match &self.root_dir {
+            Some(root_dir) if !self.storage.is_initialized() => {
+                self.storage = format.storage(root_dir);
+            }
+            _ => (),
+        }
+        self
+    }
+
+    /// Opens the repository even if other users can access its root,
+    /// instead of refusing to unlock it
+    pub fn allow_insecure(mut self, allow_insecure: bool) -> Self {
+        self.allow_insecure = allow_insecure;
+        self
+    }
+
+    /// How long to wait for other processes using the repository before
+    /// failing with [`RepositoryError::Busy`]
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock = self.lock.timeout(timeout);
+        self
+    }
 
-        if self.get_latest_version(&password_folder).is_ok() {
-            eprintln!("pwm: Password already exists. To update en existing password run:\n\n  `pwm update <PASSWORD_NAME> [PASSWORD VALUE]`");
-            std::process::exit(1);
+    /// Checks that the repository root belongs to the current user and
+    /// that nobody else can access it
+    pub fn check_permissions(&self) -> Result<(), RepositoryError> {
+        let Some(root_dir) = &self.root_dir else {
+            return Ok(());
         };
+        match permissions::exposure(root_dir)? {
+            Some(reason) => Err(RepositoryError::Insecure(reason)),
+            None => Ok(()),
+        }
+    }
 
-        fs::create_dir_all(&password_folder).expect("Expect");
+    /// Creates the repository root and its vault header, leaving the
+    /// repository unlocked with the new master password. Roots holding
+    /// unencrypted passwords are refused.
+    pub fn init(
+        &mut self,
+        master_password: &str,
+    ) -> Result<(), RepositoryError> {
+        if self.is_initialized() {
+            return Err(RepositoryError::AlreadyInitialized);
+        }
+
+        if let Some(root_dir) = &self.root_dir {
+            permissions::create_dir_all(root_dir)?;
+            permissions::restrict_folder(root_dir)?;
+        }
+        // Another process may have initialized it in the meantime
+        let _lock = self.lock.exclusive()?;
+        if self.is_initialized() {
+            return Err(RepositoryError::AlreadyInitialized);
+        }
+        // Stores written before encryption hold plaintext versions, which
+        // would be taken for corrupt ciphertext once the header exists
+        if !self.storage.encrypts_names() {
+            let unencrypted = self.storage.names()?.len();
+            if unencrypted > 0 {
+                return Err(RepositoryError::Unencrypted(unencrypted));
+            }
+        }
+        let (header, key) = VaultHeader::create(master_password)?;
+        let key = Rc::new(key);
+        self.storage.open(&key);
+        self.storage.create(&header)?;
+        self.key = Some(key);
+        Ok(())
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.storage.is_initialized()
+    }
+
+    /// Whether even the names of the passwords are encrypted, so that the
+    /// repository must be unlocked to list them
+    pub fn encrypts_names(&self) -> bool {
+        self.storage.encrypts_names()
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Derives the vault key from the master password. Must be called before
+    /// reading or writing any password.
+    pub fn unlock(
+        &mut self,
+        master_password: &str,
+    ) -> Result<(), RepositoryError> {
+        if !self.is_initialized() {
+            return Err(RepositoryError::NotInitialized);
+        }
+        if !self.allow_insecure {
+            self.check_permissions()?;
+        }
+
+        self.recover()?;
+        let header = {
+            let _lock = self.lock.shared()?;
+            self.storage.read_header()?
+        };
+        let key = Rc::new(header.unlock(master_password)?);
+        self.storage.open(&key);
+        self.key = Some(key);
+        Ok(())
+    }
+
+    /// Finishes or undoes the changes a crash interrupted. `unlock` calls
+    /// it, the names of a repository listed without unlocking it should
+    /// only be read after it.
+    pub fn recover(&self) -> Result<(), RepositoryError> {
+        if !self.storage.interrupted()? {
+            return Ok(());
+        }
+        let _lock = self.lock.exclusive()?;
+        self.storage.recover()
+    }
+
+    fn key(&self) -> Result<&VaultKey, RepositoryError> {
+        self.key.as_deref().ok_or(RepositoryError::Locked)
+    }
+
+    pub fn add(
+        &self,
+        password_name: &PasswordName,
+        password: &Password,
+        metadata: VersionMetadata,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        match self.get_latest_version(password_name) {
+            Ok(_) => {
+                return Err(RepositoryError::AlreadyExists(
+                    password_name.to_string(),
+                ))
+            }
+            Err(RepositoryError::NotFound(_)) => (),
+            Err(error) => return Err(error),
+        };
 
         self.write_password_version(
-            &password_folder,
+            password_name,
             PasswordVersion::new(password.to_owned(), 1),
-        );
+            metadata,
+        )
     }
 
     pub fn get(
         &self,
-        password_name: &str,
+        password_name: &PasswordName,
         options: GetFlags,
-    ) -> Result<PasswordVersion, Box<dyn std::error::Error>> {
-        let password_folder = self.root_dir.join(Path::new(password_name));
+    ) -> Result<PasswordVersion, RepositoryError> {
+        let _lock = self.lock.shared()?;
         let version = match options.version {
-            None => self.get_latest_version(&password_folder)?,
+            None => self.get_latest_version(password_name)?,
             Some(version) => version,
         };
 
-        let password_path =
-            password_folder.join(Path::new(version.to_string().as_str()));
+        let Some(encrypted) =
+            self.storage.read_version(password_name, version)?
+        else {
+            self.get_latest_version(password_name)?;
+            return Err(RepositoryError::VersionNotFound {
+                name: password_name.to_string(),
+                version,
+            });
+        };
+        let password = Password::from_record(
+            password_name.to_string(),
+            self.key()?.decrypt(
+                &encrypted,
+                AssociatedData::Version {
+                    name: password_name,
+                    version,
+                },
+            )?,
+        )?;
+        Ok(PasswordVersion::new(password, version))
+    }
 
-        let password_value = fs::read_to_string(password_path)?;
-        let password =
-            Password::new(password_name.to_string(), password_value);
+    fn get_latest_version(
+        &self,
+        password_name: &PasswordName,
+    ) -> Result<u32, RepositoryError> {
+        match self.versions(password_name)?.last() {
+            Some(version) => Ok(*version),
+            None => Err(RepositoryError::NotFound(password_name.to_string())),
+        }
+    }
+
+    /// Version numbers stored for a password, in ascending order
+    pub fn versions(
+        &self,
+        password_name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        self.storage.versions(password_name)
+    }
+
+    pub fn update(
+        &self,
+        password_name: &PasswordName,
+        password: &Password,
+        metadata: VersionMetadata,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let version = self.get_latest_version(password_name)? + 1;
+
+        self.write_password_version(
+            password_name,
+            PasswordVersion::new(password.to_owned(), version),
+            metadata,
+        )
+    }
+
+    /// Writes a new version made by `change` from the latest one. Both are
+    /// read and written under one exclusive lock, so that concurrent
+    /// changes to the password are never lost.
+    pub fn modify<F>(
+        &self,
+        password_name: &PasswordName,
+        change: F,
+    ) -> Result<PasswordVersion, RepositoryError>
+    where
+        F: FnOnce(
+            &Password,
+        )
+            -> Result<(Password, VersionMetadata), RepositoryError>,
+    {
+        let _lock = self.lock.exclusive()?;
+        let latest = self.get(password_name, GetFlags::new())?;
+        let (password, metadata) = change(latest.password())?;
+        self.update(password_name, &password, metadata)?;
+        let version = self.get_latest_version(password_name)?;
         Ok(PasswordVersion::new(password, version))
     }
 
-    fn get_latest_version<P: AsRef<Path>>(
+    /// Writes the content of an old version as a new latest version, so
+    /// that history is kept, recording the rollback in its metadata. HOTP
+    /// counters keep their latest value.
+    pub fn rollback(
+        &self,
+        password_name: &PasswordName,
+        version: u32,
+        message: Option<String>,
+    ) -> Result<PasswordVersion, RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let flags = GetFlags {
+            version: Some(version),
+            ..GetFlags::new()
+        };
+        let mut password = self.get(password_name, flags)?.password().clone();
+        let latest = self.get(password_name, GetFlags::new())?;
+        if let (Some(old), Some(current)) =
+            (password.otp(), latest.password().otp())
+        {
+            let otp = otp::with_latest_counter(old, current);
+            password = password.with_otp(otp);
+        }
+        // The restored value keeps the settings it was generated with, so
+        // that a later rotation generates the same kind of value
+        let generator = self
+            .get_metadata(password_name, version)?
+            .and_then(|metadata| metadata.generator().cloned());
+        let metadata = VersionMetadata::new()
+            .with_message(message)
+            .with_generator(generator)
+            .with_rollback_of(Some(version));
+
+        self.update(password_name, &password, metadata)?;
+        let latest = self.get_latest_version(password_name)?;
+        Ok(PasswordVersion::new(password, latest))
+    }
+
+    fn write_password_version(
         &self,
-        password_folder: P,
-    ) -> Result<u32, Box<dyn std::error::Error>> {
-        let mut current_version: u32 = 0;
+        password_name: &PasswordName,
+        password_version: PasswordVersion,
+        metadata: VersionMetadata,
+    ) -> Result<(), RepositoryError> {
+        let key = self.key()?;
+        let version = password_version.version();
+        let encrypted = key.encrypt(
+            &password_version.password().to_record(),
+            AssociatedData::Version {
+                name: password_name,
+                version,
+            },
+        );
+        let serialized_metadata = serde_json::to_vec(&metadata)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
 
-        for entry in fs::read_dir(&password_folder)? {
-            let entry = entry?;
-            let version = match entry.file_name().into_string() {
-                Ok(version) => version.parse::<u32>()?,
-                Err(_) => continue,
-            };
+        self.storage.write_version(
+            password_name,
+            version,
+            &encrypted,
+            &key.encrypt(
+                &serialized_metadata,
+                AssociatedData::Metadata {
+                    name: password_name,
+                    version,
+                },
+            ),
+        )
+    }
 
-            if current_version < version {
-                current_version = version;
-            }
+    /// Metadata of a password version, if it was written with any
+    pub fn get_metadata(
+        &self,
+        password_name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<VersionMetadata>, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        let Some(encrypted) =
+            self.storage.read_metadata(password_name, version)?
+        else {
+            return Ok(None);
+        };
+        let serialized = self.key()?.decrypt(
+            &encrypted,
+            AssociatedData::Metadata {
+                name: password_name,
+                version,
+            },
+        )?;
+        let metadata = serde_json::from_slice(&serialized)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+        Ok(Some(metadata))
+    }
+
+    /// Every version of a password with its metadata, newest first
+    pub fn history(
+        &self,
+        password_name: &PasswordName,
+    ) -> Result<Vec<(u32, Option<VersionMetadata>)>, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        let mut versions = self.versions(password_name)?;
+        versions.reverse();
+
+        versions
+            .into_iter()
+            .map(|version| {
+                Ok((version, self.get_metadata(password_name, version)?))
+            })
+            .collect()
+    }
+
+    /// Names of every password, including the nested ones, sorted
+    pub fn list(&self) -> Result<Vec<PasswordName>, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        if !self.is_initialized() {
+            return Err(RepositoryError::NotInitialized);
         }
 
-        if current_version == 0 {
-            Err(Box::new(io::Error::from(io::ErrorKind::NotFound)))
-        } else {
-            Ok(current_version)
+        let mut names = self.storage.names()?;
+        names.sort();
+        Ok(names)
+    }
+
+    /// Names of the passwords in a folder, and the folder itself if it is
+    /// a password too
+    pub fn list_under(
+        &self,
+        prefix: &PasswordName,
+    ) -> Result<Vec<PasswordName>, RepositoryError> {
+        let folder = format!("{prefix}{SEPARATOR}");
+        let names: Vec<PasswordName> = self
+            .list()?
+            .into_iter()
+            .filter(|name| {
+                name == prefix || name.as_str().starts_with(&folder)
+            })
+            .collect();
+        if names.is_empty() {
+            return Err(RepositoryError::NotFound(prefix.to_string()));
         }
+        Ok(names)
     }
 
-    pub fn update(&self, password: &Password) {
-        let password_folder = self.root_dir.join(Path::new(password.name()));
-        let version = match self.get_latest_version(&password_folder) {
-            Ok(version) => version + 1,
-            Err(_) => {
-                eprintln!("pwm: Password does not exist. To create a new password run:\n\n  `pwm new <PASSWORD_NAME> [PASSWORD_VALUE]`");
-                std::process::exit(1);
-            }
+    /// Rotation policy of the repository, empty if none was configured
+    pub fn rotation_policy(&self) -> Result<RotationPolicy, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        let Some(encrypted) =
+            self.storage.read_setting(ROTATION_POLICY_SETTING)?
+        else {
+            return Ok(RotationPolicy::new());
         };
+        let serialized = self.key()?.decrypt(
+            &encrypted,
+            AssociatedData::Setting(ROTATION_POLICY_SETTING),
+        )?;
+        serde_json::from_slice(&serialized)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))
+    }
 
-        self.write_password_version(
-            &password_folder,
-            PasswordVersion::new(password.to_owned(), version),
+    pub fn set_rotation_policy(
+        &self,
+        policy: &RotationPolicy,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let serialized = serde_json::to_vec(policy)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+        self.storage.write_setting(
+            ROTATION_POLICY_SETTING,
+            &self.key()?.encrypt(
+                &serialized,
+                AssociatedData::Setting(ROTATION_POLICY_SETTING),
+            ),
+        )
+    }
+
+    /// Moves every version of a password to the trash, from where it can
+    /// be restored until the trash is emptied. Passwords nested under its
+    /// name are kept.
+    pub fn remove(
+        &self,
+        password_name: &PasswordName,
+    ) -> Result<TrashEntry, RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        if self.versions(password_name)?.is_empty() {
+            return Err(RepositoryError::NotFound(password_name.to_string()));
+        }
+
+        // Identifiers sort in the order the passwords were removed, even
+        // within the same second
+        let removed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let entry = TrashEntry::new(
+            password_name,
+            removed_at.as_secs(),
+            format!("{:020}-{:08x}", removed_at.as_nanos(), OsRng.next_u32()),
         );
+        self.storage.trash_entry(password_name, &entry)?;
+        Ok(entry)
     }
 
-    fn write_password_version<P: AsRef<Path>>(
+    /// Deletes every version of a password without going through the
+    /// trash, keeping the passwords nested under its name
+    pub fn remove_permanently(
         &self,
-        password_folder: P,
-        password_version: PasswordVersion,
-    ) {
-        let password_file = password_folder
-            .as_ref()
-            .join(Path::new(password_version.version().to_string().as_str()));
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&password_file)
-            .expect("Couldn't open file");
-
-        write!(file, "{}", password_version.password().value())
-            .expect("Couldn't save password");
-    }
-
-    pub fn list(&self) {
-        let paths = match fs::read_dir(&self.root_dir) {
-            Ok(paths) => paths,
-            Err(_) => {
-                eprintln!(
-                    "Root path {} not found. Try running\npwm init\n.",
-                    self.root_dir.display()
-                );
-                return;
+        password_name: &PasswordName,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        if self.versions(password_name)?.is_empty() {
+            return Err(RepositoryError::NotFound(password_name.to_string()));
+        }
+        self.storage.delete_entry(password_name)
+    }
+
+    /// Deletes the versions of a password that the retention rules don't
+    /// keep, returning their numbers in ascending order. With `dry_run`
+    /// nothing is deleted.
+    pub fn prune(
+        &self,
+        password_name: &PasswordName,
+        retention: &Retention,
+        dry_run: bool,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let now = unix_now();
+        let mut pruned = Vec::new();
+        for (position, (version, metadata)) in
+            self.history(password_name)?.into_iter().enumerate()
+        {
+            let created_at =
+                metadata.as_ref().map(VersionMetadata::created_at);
+            if !retention.keeps(position, created_at, now) {
+                pruned.push(version);
             }
+        }
+        pruned.reverse();
+
+        if !dry_run && !pruned.is_empty() {
+            self.storage.delete_versions(password_name, &pruned)?;
+        }
+        Ok(pruned)
+    }
+
+    /// Passwords in the trash, oldest deletion first
+    pub fn trash(&self) -> Result<Vec<TrashEntry>, RepositoryError> {
+        let _lock = self.lock.shared()?;
+        if !self.is_initialized() {
+            return Err(RepositoryError::NotInitialized);
+        }
+
+        let mut trash = self.storage.trashed()?;
+        trash.sort_by(|first, second| first.id().cmp(second.id()));
+        Ok(trash)
+    }
+
+    /// Moves the most recently removed password with this name back out of
+    /// the trash, as long as no other password took its name
+    pub fn restore(
+        &self,
+        password_name: &PasswordName,
+    ) -> Result<TrashEntry, RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let Some(entry) = self
+            .trash()?
+            .into_iter()
+            .rfind(|entry| entry.name() == password_name.as_str())
+        else {
+            return Err(RepositoryError::NotFound(password_name.to_string()));
         };
+        match self.versions(password_name) {
+            Ok(versions) if !versions.is_empty() => {
+                return Err(RepositoryError::AlreadyExists(
+                    password_name.to_string(),
+                ))
+            }
+            Ok(_) | Err(RepositoryError::NotFound(_)) => (),
+            Err(error) => return Err(error),
+        }
+
+        self.storage.restore_entry(&entry)?;
+        Ok(entry)
+    }
 
-        for path in paths {
-            let path = path.expect("Failed to read file names");
-            println!("{}", path.file_name().to_str().unwrap());
+    /// Permanently deletes the passwords in the trash, or only the ones
+    /// removed at least `older_than` seconds ago, returning them
+    pub fn empty_trash(
+        &self,
+        older_than: Option<u64>,
+    ) -> Result<Vec<TrashEntry>, RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        let now = unix_now();
+        let mut emptied = Vec::new();
+        for entry in self.trash()? {
+            if older_than.is_some_and(|age| !entry.is_older_than(age, now)) {
+                continue;
+            }
+            self.storage.delete_trashed(&entry)?;
+            emptied.push(entry);
         }
+        Ok(emptied)
     }
 
-    pub fn remove(&self, password_name: &str) {
-        let mut root_path = self.root_dir.clone();
-        root_path.push(password_name);
+    /// Moves every version of a password and its metadata to a new name.
+    /// Passwords nested under the old name are kept where they are.
+    pub fn rename(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        force: bool,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        self.check_destination(source, destination, force)?;
+        let versions = self.versions_for(source, destination)?;
+        self.storage.move_entry(source, destination, &versions)
+    }
 
-        if root_path.exists() && root_path.is_dir() {
-            fs::remove_dir_all(&root_path).unwrap();
+    /// Duplicates every version of a password and its metadata under a new
+    /// name. An existing password is only replaced with `force`.
+    pub fn copy(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        force: bool,
+    ) -> Result<(), RepositoryError> {
+        let _lock = self.lock.exclusive()?;
+        self.check_destination(source, destination, force)?;
+        let versions = self.versions_for(source, destination)?;
+        self.storage.write_entry(destination, &versions)
+    }
+
+    /// Every version of `source` with its metadata, encrypted again for
+    /// `destination` as values are bound to where they are stored
+    fn versions_for(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+    ) -> Result<Vec<StoredVersion>, RepositoryError> {
+        let key = self.key()?;
+        let reseal = |encrypted: &[u8], from, to| {
+            Ok::<_, RepositoryError>(
+                key.encrypt(&key.decrypt(encrypted, from)?, to),
+            )
+        };
+        self.versions(source)?
+            .into_iter()
+            .map(|version| {
+                let record = self
+                    .storage
+                    .read_version(source, version)?
+                    .ok_or_else(|| RepositoryError::VersionNotFound {
+                        name: source.to_string(),
+                        version,
+                    })?;
+                let metadata = self
+                    .storage
+                    .read_metadata(source, version)?
+                    .map(|metadata| {
+                        reseal(
+                            &metadata,
+                            AssociatedData::Metadata {
+                                name: source,
+                                version,
+                            },
+                            AssociatedData::Metadata {
+                                name: destination,
+                                version,
+                            },
+                        )
+                    })
+                    .transpose()?;
+                Ok(StoredVersion {
+                    version,
+                    record: reseal(
+                        &record,
+                        AssociatedData::Version {
+                            name: source,
+                            version,
+                        },
+                        AssociatedData::Version {
+                            name: destination,
+                            version,
+                        },
+                    )?,
+                    metadata,
+                })
+            })
+            .collect()
+    }
+
+    /// Checks that a password can be copied or renamed to `destination`
+    fn check_destination(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        force: bool,
+    ) -> Result<(), RepositoryError> {
+        if !self.is_initialized() {
+            return Err(RepositoryError::NotInitialized);
+        }
+        if self.versions(source)?.is_empty() {
+            return Err(RepositoryError::NotFound(source.to_string()));
+        }
+        let exists = match self.versions(destination) {
+            Ok(existing) => !existing.is_empty(),
+            Err(RepositoryError::NotFound(_)) => false,
+            Err(error) => return Err(error),
+        };
+        if source == destination || (exists && !force) {
+            return Err(RepositoryError::AlreadyExists(
+                destination.to_string(),
+            ));
         }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::storage::{MemoryStorage, VAULT_FILE};
+    use crate::cli::vault::HEADER_FILE;
+    use std::fs;
+    use std::path::Path;
 
     const PASSWORD_NAME: &str = "test-password";
     const PASSWORD_VALUE: &str = "TEST";
     const NEW_PASSWORD_VALUE: &str = "NEW-TEST";
 
+    const MASTER_PASSWORD: &str = "master";
+
+    const SECONDS_PER_HOUR: u64 = 3_600;
+
+    fn password_name() -> PasswordName {
+        PasswordName::new(PASSWORD_NAME).unwrap()
+    }
+
     #[test]
     fn save_and_update_new_password() {
-        let password_repo = PasswordRepository::new();
+        let mut password_repo =
+            PasswordRepository::with_storage(MemoryStorage::new());
+        password_repo.init(MASTER_PASSWORD).unwrap();
 
         let password = Password::new(
             PASSWORD_NAME.to_string(),
@@ -167,7 +766,10 @@ mod tests {
         let mut original_options = GetFlags::new();
         original_options.version = Some(1);
 
-        password_repo.remove(&password.name());
+        assert!(matches!(
+            password_repo.remove(&password_name()),
+            Err(RepositoryError::NotFound(_))
+        ));
 
         let new_password = Password::new(
             PASSWORD_NAME.to_string(),
@@ -178,22 +780,899 @@ mod tests {
         let mut new_options = GetFlags::new();
         new_options.version = Some(2);
 
-        password_repo.add(&password);
-        password_repo.update(&new_password);
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        password_repo
+            .update(&password_name(), &new_password, VersionMetadata::new())
+            .unwrap();
 
         assert_eq!(
             password_version,
             password_repo
-                .get(&new_password.name(), original_options)
+                .get(&password_name(), original_options)
                 .expect("Couldn't get value")
         );
         assert_eq!(
             new_password_version,
             password_repo
-                .get(&new_password.name(), new_options)
+                .get(&password_name(), new_options)
                 .expect("Couldn't get value")
         );
 
-        password_repo.remove(new_password.name());
+        password_repo.remove(&password_name()).unwrap();
+        assert!(matches!(
+            password_repo.get(&password_name(), GetFlags::new()),
+            Err(RepositoryError::NotFound(_))
+        ));
+        password_repo.restore(&password_name()).unwrap();
+        assert_eq!(
+            password_repo
+                .get(&password_name(), GetFlags::new())
+                .unwrap(),
+            new_password_version
+        );
+    }
+
+    #[test]
+    fn values_are_encrypted_at_rest() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+
+        let stored =
+            fs::read(root_dir.path().join(PASSWORD_NAME).join("1")).unwrap();
+        assert!(!stored
+            .windows(PASSWORD_VALUE.len())
+            .any(|window| window == PASSWORD_VALUE.as_bytes()));
+
+        let mut reopened = PasswordRepository::with_root_dir(root_dir.path());
+        assert!(matches!(
+            reopened.get(&password_name(), GetFlags::new()),
+            Err(RepositoryError::Locked)
+        ));
+        assert!(matches!(
+            reopened.unlock("not the master password"),
+            Err(RepositoryError::WrongMasterPassword)
+        ));
+        reopened.unlock(MASTER_PASSWORD).unwrap();
+        assert_eq!(
+            reopened.get(&password_name(), GetFlags::new()).unwrap(),
+            PasswordVersion::new(password, 1)
+        );
+    }
+
+    #[test]
+    fn typed_errors() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        assert!(matches!(
+            password_repo.unlock(MASTER_PASSWORD),
+            Err(RepositoryError::NotInitialized)
+        ));
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        assert!(matches!(
+            password_repo.init(MASTER_PASSWORD),
+            Err(RepositoryError::AlreadyInitialized)
+        ));
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        assert!(matches!(
+            password_repo.update(
+                &password_name(),
+                &password,
+                VersionMetadata::new()
+            ),
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            password_repo.get(&password_name(), GetFlags::new()),
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        assert!(matches!(
+            password_repo.add(
+                &password_name(),
+                &password,
+                VersionMetadata::new()
+            ),
+            Err(RepositoryError::AlreadyExists(_))
+        ));
+
+        let mut options = GetFlags::new();
+        options.version = Some(2);
+        assert!(matches!(
+            password_repo.get(&password_name(), options),
+            Err(RepositoryError::VersionNotFound { version: 2, .. })
+        ));
+
+        fs::write(root_dir.path().join(PASSWORD_NAME).join("1"), "garbage")
+            .unwrap();
+        assert!(matches!(
+            password_repo.get(&password_name(), GetFlags::new()),
+            Err(RepositoryError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn history_with_metadata() {
+        let mut password_repo =
+            PasswordRepository::with_storage(MemoryStorage::new());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        let new_password = Password::new(
+            PASSWORD_NAME.to_string(),
+            NEW_PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        password_repo
+            .update(
+                &password_name(),
+                &new_password,
+                VersionMetadata::new().with_message(Some("Rotated".into())),
+            )
+            .unwrap();
+        assert_eq!(
+            password_repo
+                .get(&password_name(), GetFlags::new())
+                .unwrap()
+                .version(),
+            2
+        );
+
+        let history = password_repo.history(&password_name()).unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|(version, _)| *version)
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        let latest = history[0].1.as_ref().unwrap();
+        assert_eq!(latest.message(), Some("Rotated"));
+        assert!(latest.created_at() > 0);
+        assert_eq!(history[1].1.as_ref().unwrap().message(), None);
+    }
+
+    #[test]
+    fn swapped_records_are_rejected() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let name = |name: &str| PasswordName::new(name).unwrap();
+        for password_name in ["bank", "mail"] {
+            let password = Password::new(
+                password_name.to_string(),
+                PASSWORD_VALUE.to_string(),
+            );
+            password_repo
+                .add(&name(password_name), &password, VersionMetadata::new())
+                .unwrap();
+            password_repo
+                .update(
+                    &name(password_name),
+                    &password,
+                    VersionMetadata::new(),
+                )
+                .unwrap();
+        }
+
+        let file = |path: &str| root_dir.path().join(path);
+        fs::copy(file("mail/1"), file("bank/2")).unwrap();
+        fs::copy(file("mail/2"), file("mail/1")).unwrap();
+        fs::copy(file("bank/1.meta"), file("bank/1")).unwrap();
+        for (password_name, version) in [("bank", 2), ("mail", 1), ("bank", 1)]
+        {
+            let flags = GetFlags {
+                version: Some(version),
+                ..GetFlags::new()
+            };
+            assert!(matches!(
+                password_repo.get(&name(password_name), flags),
+                Err(RepositoryError::Corrupt(_))
+            ));
+        }
+        fs::copy(file("mail/2.meta"), file("mail/1.meta")).unwrap();
+        assert!(matches!(
+            password_repo.get_metadata(&name("mail"), 1),
+            Err(RepositoryError::Corrupt(_))
+        ));
+        assert!(password_repo.get_metadata(&name("mail"), 2).is_ok());
+    }
+
+    #[test]
+    fn legacy_versions_keep_loading() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let password_folder = root_dir.path().join(PASSWORD_NAME);
+        fs::create_dir_all(&password_folder).unwrap();
+        let encrypted = password_repo.key().unwrap().encrypt(
+            PASSWORD_VALUE.as_bytes(),
+            AssociatedData::Version {
+                name: &password_name(),
+                version: 1,
+            },
+        );
+        fs::write(password_folder.join("1"), encrypted).unwrap();
+
+        assert_eq!(
+            password_repo
+                .get(&password_name(), GetFlags::new())
+                .unwrap(),
+            PasswordVersion::new(
+                Password::new(
+                    PASSWORD_NAME.to_string(),
+                    PASSWORD_VALUE.to_string()
+                ),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn names_stay_inside_the_repository() {
+        let parent = tempfile::tempdir().unwrap();
+        let root_dir = parent.path().join("store");
+        let mut password_repo = PasswordRepository::with_root_dir(&root_dir);
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let victim = parent.path().join("victim");
+        fs::create_dir_all(&victim).unwrap();
+        fs::write(victim.join("1"), "keep me").unwrap();
+
+        // Names are validated before the repository is ever reached
+        for name in ["../victim", "/tmp/absolute", "bad\nname", "CON"] {
+            assert!(matches!(
+                PasswordName::new(name),
+                Err(RepositoryError::InvalidName { .. })
+            ));
+        }
+        assert_eq!(fs::read(victim.join("1")).unwrap(), b"keep me");
+
+        // Folders created behind the repository's back are not trusted
+        fs::create_dir_all(root_dir.join("bad\name")).unwrap();
+        assert!(matches!(
+            password_repo.list(),
+            Err(RepositoryError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rename_and_copy() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let name = |name: &str| PasswordName::new(name).unwrap();
+
+        for (password_name, value) in [
+            ("work", PASSWORD_VALUE),
+            ("work/vpn", PASSWORD_VALUE),
+            ("personal", PASSWORD_VALUE),
+        ] {
+            let password =
+                Password::new(password_name.to_string(), value.to_string());
+            password_repo
+                .add(&name(password_name), &password, VersionMetadata::new())
+                .unwrap();
+        }
+        let new_password =
+            Password::new("work".to_string(), NEW_PASSWORD_VALUE.to_string());
+        password_repo
+            .update(
+                &name("work"),
+                &new_password,
+                VersionMetadata::new().with_message(Some("Rotated".into())),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            password_repo.rename(&name("missing"), &name("other"), false),
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert!(matches!(
+            password_repo.rename(&name("work"), &name("personal"), false),
+            Err(RepositoryError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            password_repo.copy(&name("work"), &name("work"), true),
+            Err(RepositoryError::AlreadyExists(_))
+        ));
+
+        password_repo
+            .copy(&name("work"), &name("backup/work"), false)
+            .unwrap();
+        password_repo
+            .rename(&name("work"), &name("personal"), true)
+            .unwrap();
+        for copied in ["backup/work", "personal"] {
+            let history = password_repo.history(&name(copied)).unwrap();
+            assert_eq!(history.len(), 2);
+            assert_eq!(
+                history[0].1.as_ref().unwrap().message(),
+                Some("Rotated")
+            );
+            assert_eq!(
+                password_repo
+                    .get(&name(copied), GetFlags::new())
+                    .unwrap()
+                    .password(),
+                &new_password.clone().with_name(copied.to_string())
+            );
+        }
+
+        assert!(matches!(
+            password_repo.get(&name("work"), GetFlags::new()),
+            Err(RepositoryError::NotFound(_))
+        ));
+        assert_eq!(
+            password_repo.list().unwrap(),
+            [name("backup/work"), name("personal"), name("work/vpn")]
+        );
+
+        // Passwords nested under either name stay in place, and replaced
+        // ones leave nothing behind
+        password_repo
+            .rename(&name("backup/work"), &name("archive/work"), false)
+            .unwrap();
+        assert!(!root_dir.path().join("backup").exists());
+        password_repo
+            .rename(&name("personal"), &name("personal/old"), false)
+            .unwrap();
+        password_repo
+            .copy(&name("work/vpn"), &name("personal/old"), true)
+            .unwrap();
+        password_repo
+            .rename(&name("archive/work"), &name("work"), false)
+            .unwrap();
+        assert_eq!(
+            password_repo.list().unwrap(),
+            [name("personal/old"), name("work"), name("work/vpn")]
+        );
+        assert_eq!(
+            password_repo.versions(&name("personal/old")).unwrap(),
+            [1]
+        );
+        assert_eq!(password_repo.versions(&name("work")).unwrap(), [1, 2]);
+        let leftovers = fs::read_dir(root_dir.path())
+            .unwrap()
+            .filter(|entry| {
+                let file_name = entry.as_ref().unwrap().file_name();
+                file_name.to_string_lossy().starts_with(".staging-")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn interrupted_moves_are_recovered() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let name = |name: &str| PasswordName::new(name).unwrap();
+        let staging_folders = || {
+            fs::read_dir(root_dir.path())
+                .unwrap()
+                .filter(|entry| {
+                    let file_name = entry.as_ref().unwrap().file_name();
+                    file_name.to_string_lossy().starts_with(".staging-")
+                })
+                .count()
+        };
+
+        for (password_name, value) in [
+            ("work", PASSWORD_VALUE),
+            ("work/vpn", PASSWORD_VALUE),
+            ("personal", NEW_PASSWORD_VALUE),
+        ] {
+            let password =
+                Password::new(password_name.to_string(), value.to_string());
+            password_repo
+                .add(&name(password_name), &password, VersionMetadata::new())
+                .unwrap();
+        }
+        let work = Password::new("work".to_string(), PASSWORD_VALUE.repeat(2));
+        password_repo
+            .update(&name("work"), &work, VersionMetadata::new())
+            .unwrap();
+
+        // A change that crashed before its journal was written is undone
+        let uncommitted = root_dir.path().join(".staging-0123456789abcdef");
+        fs::create_dir(&uncommitted).unwrap();
+        fs::write(uncommitted.join("1"), "partial").unwrap();
+        password_repo.recover().unwrap();
+        assert_eq!(staging_folders(), 0);
+        assert_eq!(password_repo.versions(&name("work")).unwrap(), [1, 2]);
+
+        // A folder in the way of the first staged file makes the move fail
+        // after the versions the destination had were deleted
+        let obstacle = root_dir.path().join("work/1.meta");
+        fs::remove_file(&obstacle).unwrap();
+        fs::create_dir(&obstacle).unwrap();
+        assert!(password_repo
+            .rename(&name("personal"), &name("work"), true)
+            .is_err());
+        assert_eq!(staging_folders(), 1);
+        fs::remove_dir(&obstacle).unwrap();
+
+        // The next unlock finishes it, keeping the nested password
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.unlock(MASTER_PASSWORD).unwrap();
+        assert_eq!(staging_folders(), 0);
+        assert_eq!(
+            password_repo.list().unwrap(),
+            [name("work"), name("work/vpn")]
+        );
+        assert_eq!(password_repo.versions(&name("work")).unwrap(), [1]);
+        assert_eq!(
+            password_repo
+                .get(&name("work"), GetFlags::new())
+                .unwrap()
+                .password(),
+            &Password::new("work".to_string(), NEW_PASSWORD_VALUE.to_string())
+        );
+    }
+
+    #[test]
+    fn trash_and_restore() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let name = |name: &str| PasswordName::new(name).unwrap();
+        let add = |password_name: &str, value: &str| {
+            let password =
+                Password::new(password_name.to_string(), value.to_string());
+            password_repo
+                .add(&name(password_name), &password, VersionMetadata::new())
+                .unwrap();
+        };
+
+        add("work", PASSWORD_VALUE);
+        add("work/vpn", PASSWORD_VALUE);
+        let entry = password_repo.remove(&name("work")).unwrap();
+        assert_eq!(entry.name(), "work");
+        assert_eq!(password_repo.list().unwrap(), [name("work/vpn")]);
+        assert_eq!(password_repo.trash().unwrap(), [entry]);
+
+        add("work", NEW_PASSWORD_VALUE);
+        assert!(matches!(
+            password_repo.restore(&name("work")),
+            Err(RepositoryError::AlreadyExists(_))
+        ));
+        password_repo.remove(&name("work")).unwrap();
+        assert_eq!(password_repo.trash().unwrap().len(), 2);
+
+        // The latest removal is restored first
+        password_repo.restore(&name("work")).unwrap();
+        assert_eq!(
+            password_repo
+                .get(&name("work"), GetFlags::new())
+                .unwrap()
+                .password()
+                .value(),
+            NEW_PASSWORD_VALUE
+        );
+        assert!(matches!(
+            password_repo.restore(&name("missing")),
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        password_repo.remove_permanently(&name("work/vpn")).unwrap();
+        assert_eq!(password_repo.trash().unwrap().len(), 1);
+        assert!(password_repo
+            .empty_trash(Some(SECONDS_PER_HOUR))
+            .unwrap()
+            .is_empty());
+        assert_eq!(password_repo.empty_trash(None).unwrap().len(), 1);
+        assert!(password_repo.trash().unwrap().is_empty());
+        assert!(matches!(
+            password_repo.restore(&name("work/vpn")),
+            Err(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn memory_storage() {
+        let mut password_repo =
+            PasswordRepository::with_storage(MemoryStorage::new());
+        assert!(!password_repo.is_initialized());
+        assert!(matches!(
+            password_repo.unlock(MASTER_PASSWORD),
+            Err(RepositoryError::NotInitialized)
+        ));
+        // Formats only apply to repositories in a root folder
+        password_repo = password_repo.storage_format(StorageFormat::Vault);
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        assert!(!password_repo.encrypts_names());
+        password_repo.check_permissions().unwrap();
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        assert_eq!(password_repo.list().unwrap(), [password_name()]);
+        assert!(matches!(
+            password_repo.unlock("wrong"),
+            Err(RepositoryError::WrongMasterPassword)
+        ));
+    }
+
+    #[test]
+    fn vault_format() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path())
+                .storage_format(StorageFormat::Vault);
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let name = |name: &str| PasswordName::new(name).unwrap();
+        let password = |password_name: &str, value: &str| {
+            Password::new(password_name.to_string(), value.to_string())
+        };
+        let value = |password_repo: &PasswordRepository, password_name| {
+            password_repo
+                .get(&name(password_name), GetFlags::new())
+                .unwrap()
+                .password()
+                .value()
+                .to_string()
+        };
+
+        password_repo
+            .add(
+                &name("work/vpn"),
+                &password("work/vpn", PASSWORD_VALUE),
+                VersionMetadata::new(),
+            )
+            .unwrap();
+        password_repo
+            .update(
+                &name("work/vpn"),
+                &password("work/vpn", NEW_PASSWORD_VALUE),
+                VersionMetadata::new().with_message(Some("rotated".into())),
+            )
+            .unwrap();
+        password_repo
+            .copy(&name("work/vpn"), &name("bank"), false)
+            .unwrap();
+        password_repo
+            .rename(&name("bank"), &name("home/bank"), false)
+            .unwrap();
+        password_repo.rollback(&name("home/bank"), 1, None).unwrap();
+        password_repo
+            .set_rotation_policy(
+                &RotationPolicy::new().max_age(Some("work/".into()), Some(90)),
+            )
+            .unwrap();
+
+        // Reopening finds the vault, and nothing but the vault is on disk
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        assert!(password_repo.encrypts_names());
+        assert!(matches!(password_repo.list(), Err(RepositoryError::Locked)));
+        password_repo.unlock(MASTER_PASSWORD).unwrap();
+        let mut files: Vec<String> = fs::read_dir(root_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, [".lock", VAULT_FILE]);
+
+        assert_eq!(
+            password_repo.list().unwrap(),
+            [name("home/bank"), name("work/vpn")]
+        );
+        assert_eq!(password_repo.list_under(&name("work")).unwrap().len(), 1);
+        assert_eq!(value(&password_repo, "work/vpn"), NEW_PASSWORD_VALUE);
+        assert_eq!(value(&password_repo, "home/bank"), PASSWORD_VALUE);
+        let history = password_repo.history(&name("work/vpn")).unwrap();
+        assert_eq!(history[0].1.as_ref().unwrap().message(), Some("rotated"));
+        assert_eq!(
+            password_repo
+                .rotation_policy()
+                .unwrap()
+                .max_age_of("work/vpn"),
+            Some(90)
+        );
+
+        let pruned = password_repo
+            .prune(&name("home/bank"), &Retention::new().keep_last(1), false)
+            .unwrap();
+        assert_eq!(pruned, [1, 2]);
+        assert_eq!(password_repo.versions(&name("home/bank")).unwrap(), [3]);
+
+        password_repo.remove(&name("work/vpn")).unwrap();
+        assert_eq!(password_repo.list().unwrap(), [name("home/bank")]);
+        password_repo.restore(&name("work/vpn")).unwrap();
+        assert_eq!(value(&password_repo, "work/vpn"), NEW_PASSWORD_VALUE);
+        password_repo.remove(&name("home/bank")).unwrap();
+        assert_eq!(password_repo.empty_trash(None).unwrap().len(), 1);
+        password_repo.remove_permanently(&name("work/vpn")).unwrap();
+        assert!(password_repo.list().unwrap().is_empty());
+        assert!(password_repo.trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rollback_keeps_history() {
+        let mut password_repo =
+            PasswordRepository::with_storage(MemoryStorage::new());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        password_repo
+            .update(
+                &password_name(),
+                &password.clone().with_value(NEW_PASSWORD_VALUE.to_string()),
+                VersionMetadata::new(),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            password_repo.rollback(&password_name(), 5, None),
+            Err(RepositoryError::VersionNotFound { version: 5, .. })
+        ));
+        let rolled_back = password_repo
+            .rollback(&password_name(), 1, Some("Reverted".into()))
+            .unwrap();
+        assert_eq!(rolled_back, PasswordVersion::new(password.clone(), 3));
+        assert_eq!(
+            password_repo
+                .get(&password_name(), GetFlags::new())
+                .unwrap(),
+            rolled_back
+        );
+
+        let history = password_repo.history(&password_name()).unwrap();
+        assert_eq!(history.len(), 3);
+        let latest = history[0].1.as_ref().unwrap();
+        assert_eq!(latest.rollback_of(), Some(1));
+        assert_eq!(latest.message(), Some("Reverted"));
+        assert_eq!(history[1].1.as_ref().unwrap().rollback_of(), None);
+    }
+
+    #[test]
+    fn rollback_keeps_hotp_counter() {
+        let mut password_repo =
+            PasswordRepository::with_storage(MemoryStorage::new());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let uri = "otpauth://hotp/Example?secret=JBSWY3DPEHPK3PXP&counter=4";
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        )
+        .with_otp(uri.to_string());
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        let advanced = password
+            .clone()
+            .with_value(NEW_PASSWORD_VALUE.to_string())
+            .with_otp(otp::with_counter(uri, 7));
+        password_repo
+            .update(&password_name(), &advanced, VersionMetadata::new())
+            .unwrap();
+
+        let rolled_back =
+            password_repo.rollback(&password_name(), 1, None).unwrap();
+        assert_eq!(rolled_back.password().value(), PASSWORD_VALUE);
+        assert_eq!(rolled_back.password().otp(), advanced.otp());
+    }
+
+    #[test]
+    fn prune_keeps_numbers_monotonic() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        for _ in 0..4 {
+            password_repo
+                .update(&password_name(), &password, VersionMetadata::new())
+                .unwrap();
+        }
+
+        let retention = Retention::new().keep_last(2);
+        assert_eq!(
+            password_repo
+                .prune(&password_name(), &retention, true)
+                .unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(
+            password_repo.versions(&password_name()).unwrap(),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            password_repo
+                .prune(&password_name(), &retention, false)
+                .unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(password_repo.versions(&password_name()).unwrap(), [4, 5]);
+        assert!(!root_dir.path().join(PASSWORD_NAME).join("1.meta").exists());
+
+        // Every version is newer than an hour, but the latest one is kept
+        // even by rules that would remove everything
+        let retention = Retention::new().keep_newer_than(SECONDS_PER_HOUR);
+        assert!(password_repo
+            .prune(&password_name(), &retention, false)
+            .unwrap()
+            .is_empty());
+        let retention = Retention::new().keep_last(0);
+        assert_eq!(
+            password_repo
+                .prune(&password_name(), &retention, false)
+                .unwrap(),
+            [4]
+        );
+
+        password_repo.remove(&password_name()).unwrap();
+        password_repo.restore(&password_name()).unwrap();
+        password_repo
+            .update(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        assert_eq!(password_repo.versions(&password_name()).unwrap(), [5, 6]);
+    }
+
+    #[test]
+    fn failed_writes_leave_no_trace() {
+        let root_dir = tempfile::tempdir().unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        let entries = || fs::read_dir(root_dir.path()).unwrap().count();
+        let before = entries();
+
+        // Without the key the first version can't be encrypted
+        let locked = PasswordRepository::with_root_dir(root_dir.path());
+        let password = Password::new(
+            PASSWORD_NAME.to_string(),
+            PASSWORD_VALUE.to_string(),
+        );
+        assert!(matches!(
+            locked.add(&password_name(), &password, VersionMetadata::new()),
+            Err(RepositoryError::Locked)
+        ));
+        assert_eq!(entries(), before);
+        assert!(matches!(
+            password_repo.versions(&password_name()),
+            Err(RepositoryError::NotFound(_))
+        ));
+
+        // Temporary files left by a crash are not taken for versions
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        let password_folder = root_dir.path().join(PASSWORD_NAME);
+        fs::write(password_folder.join(".tmp-0123456789abcdef"), "partial")
+            .unwrap();
+        fs::create_dir(root_dir.path().join(".staging-0123456789abcdef"))
+            .unwrap();
+        assert_eq!(password_repo.versions(&password_name()).unwrap(), [1]);
+        assert_eq!(password_repo.list().unwrap(), [password_name()]);
+
+        // Shorter values replace longer ones without leftovers
+        password_repo
+            .update(
+                &password_name(),
+                &password.clone().with_value(NEW_PASSWORD_VALUE.repeat(10)),
+                VersionMetadata::new(),
+            )
+            .unwrap();
+        let nested = Password::new(
+            format!("{PASSWORD_NAME}/nested"),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(
+                &PasswordName::new(nested.name()).unwrap(),
+                &nested,
+                VersionMetadata::new(),
+            )
+            .unwrap();
+        password_repo.remove_permanently(&password_name()).unwrap();
+        password_repo
+            .add(&password_name(), &password, VersionMetadata::new())
+            .unwrap();
+        assert_eq!(
+            password_repo
+                .get(&password_name(), GetFlags::new())
+                .unwrap(),
+            PasswordVersion::new(password, 1)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exposed_repositories_stay_locked() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root_dir = tempfile::tempdir().unwrap();
+        let mode = |path: &Path| {
+            fs::metadata(path).unwrap().permissions().mode() & 0o777
+        };
+        fs::set_permissions(
+            root_dir.path(),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        let mut password_repo =
+            PasswordRepository::with_root_dir(root_dir.path());
+        password_repo.init(MASTER_PASSWORD).unwrap();
+        assert_eq!(mode(root_dir.path()), 0o700);
+
+        let password = Password::new(
+            format!("{PASSWORD_NAME}/nested"),
+            PASSWORD_VALUE.to_string(),
+        );
+        password_repo
+            .add(
+                &PasswordName::new(password.name()).unwrap(),
+                &password,
+                VersionMetadata::new(),
+            )
+            .unwrap();
+        let folder = root_dir.path().join(PASSWORD_NAME);
+        assert_eq!(mode(&folder), 0o700);
+        assert_eq!(mode(&folder.join("nested").join("1")), 0o600);
+        assert_eq!(mode(&root_dir.path().join(HEADER_FILE)), 0o600);
+
+        fs::set_permissions(
+            root_dir.path(),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        let mut exposed = PasswordRepository::with_root_dir(root_dir.path());
+        assert!(matches!(
+            exposed.unlock(MASTER_PASSWORD),
+            Err(RepositoryError::Insecure(_))
+        ));
+        let mut exposed = exposed.allow_insecure(true);
+        exposed.unlock(MASTER_PASSWORD).unwrap();
     }
 }
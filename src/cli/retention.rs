@@ -0,0 +1,80 @@
+/// Versions of a password that `prune` keeps. The latest version is always
+/// kept, so new versions keep numbering after it and pruned numbers are
+/// never reused. A version is kept when any of the rules keeps it.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Retention {
+    keep_last: Option<u32>,
+    /// Seconds
+    keep_newer_than: Option<u64>,
+}
+
+impl Retention {
+    pub fn new() -> Self {
+        Retention::default()
+    }
+
+    /// Keeps the newest `count` versions
+    pub fn keep_last(mut self, count: u32) -> Self {
+        self.keep_last = Some(count);
+        self
+    }
+
+    /// Keeps the versions created less than `age` seconds ago. Versions
+    /// without metadata have no known age and are not kept by this rule.
+    pub fn keep_newer_than(mut self, age: u64) -> Self {
+        self.keep_newer_than = Some(age);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keep_last.is_none() && self.keep_newer_than.is_none()
+    }
+
+    /// Whether a version is kept, from its position counting from the
+    /// newest one, which is 0, and its creation time at `now`
+    pub fn keeps(
+        &self,
+        position: usize,
+        created_at: Option<u64>,
+        now: u64,
+    ) -> bool {
+        position == 0
+            || self
+                .keep_last
+                .is_some_and(|count| position < count as usize)
+            || self.keep_newer_than.is_some_and(|age| {
+                created_at.is_some_and(|created_at| {
+                    now.saturating_sub(created_at) < age
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_combined() {
+        let now = 1_000;
+        let retention = Retention::new().keep_last(2);
+        assert!(retention.keeps(0, None, now));
+        assert!(retention.keeps(1, Some(0), now));
+        assert!(!retention.keeps(2, Some(now), now));
+
+        let retention = Retention::new().keep_newer_than(100);
+        assert!(retention.keeps(0, Some(0), now));
+        assert!(retention.keeps(5, Some(950), now));
+        assert!(!retention.keeps(5, Some(900), now));
+        assert!(!retention.keeps(5, None, now));
+
+        let retention = retention.keep_last(3);
+        assert!(retention.keeps(2, Some(0), now));
+        assert!(retention.keeps(5, Some(950), now));
+        assert!(!retention.keeps(5, Some(0), now));
+
+        // Only the latest version survives without rules
+        assert!(Retention::new().is_empty());
+        assert!(!Retention::new().keeps(1, Some(now), now));
+    }
+}
@@ -0,0 +1,136 @@
+use super::name::SEPARATOR;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Maximum age of passwords before they should be rotated, either for
+/// every password or for the ones under a prefix
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct RotationPolicy {
+    #[serde(default)]
+    max_age_days: Option<u32>,
+    #[serde(default)]
+    prefixes: BTreeMap<String, u32>,
+}
+
+impl RotationPolicy {
+    pub fn new() -> Self {
+        RotationPolicy::default()
+    }
+
+    /// Sets or clears the maximum age of every password, or of the ones
+    /// starting with `prefix`
+    pub fn max_age(
+        mut self,
+        prefix: Option<String>,
+        days: Option<u32>,
+    ) -> Self {
+        match (prefix, days) {
+            (None, days) => self.max_age_days = days,
+            (Some(prefix), Some(days)) => {
+                self.prefixes.insert(prefix, days);
+            }
+            (Some(prefix), None) => {
+                self.prefixes.remove(&prefix);
+            }
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_age_days.is_none() && self.prefixes.is_empty()
+    }
+
+    /// Maximum age of a password, from the longest prefix of its name
+    /// that has one or else the global one. Prefixes match whole segments,
+    /// so `work` applies to `work` and `work/vpn` but not to `workshop`.
+    pub fn max_age_of(&self, name: &str) -> Option<u32> {
+        self.prefixes
+            .iter()
+            .map(|(prefix, days)| (prefix.trim_end_matches(SEPARATOR), days))
+            .filter(|(prefix, _)| {
+                name.strip_prefix(prefix).is_some_and(|rest| {
+                    rest.is_empty() || rest.starts_with(SEPARATOR)
+                })
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, days)| *days)
+            .or(self.max_age_days)
+    }
+
+    /// Whether a password created at `created_at` is past its maximum age
+    /// at `now`, both in seconds since the Unix epoch
+    pub fn is_stale(&self, name: &str, created_at: u64, now: u64) -> bool {
+        self.max_age_of(name).is_some_and(|days| {
+            now.saturating_sub(created_at) > days as u64 * SECONDS_PER_DAY
+        })
+    }
+}
+
+impl std::fmt::Display for RotationPolicy {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let mut lines = Vec::new();
+        if let Some(days) = self.max_age_days {
+            lines.push(format!("*  {days} days"));
+        }
+        for (prefix, days) in &self.prefixes {
+            let folder = prefix.trim_end_matches(SEPARATOR);
+            lines.push(format!("{folder}{SEPARATOR}*  {days} days"));
+        }
+        write!(formatter, "{}", lines.join("\n"))
+    }
+}
+
+/// Whole days between two times in seconds since the Unix epoch
+pub fn age_in_days(created_at: u64, now: u64) -> u64 {
+    now.saturating_sub(created_at) / SECONDS_PER_DAY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        let policy = RotationPolicy::new()
+            .max_age(None, Some(365))
+            .max_age(Some("work".to_string()), Some(90))
+            .max_age(Some("work/vpn".to_string()), Some(30));
+        assert_eq!(policy.max_age_of("personal"), Some(365));
+        assert_eq!(policy.max_age_of("work"), Some(90));
+        assert_eq!(policy.max_age_of("work/email"), Some(90));
+        assert_eq!(policy.max_age_of("work/vpn/eu"), Some(30));
+
+        let policy = policy.max_age(None, None);
+        assert_eq!(policy.max_age_of("personal"), None);
+        let policy = policy.max_age(Some("work/vpn".to_string()), None);
+        assert_eq!(policy.max_age_of("work/vpn/eu"), Some(90));
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let policy = RotationPolicy::new()
+            .max_age(Some("work".to_string()), Some(90))
+            .max_age(Some("home/".to_string()), Some(30));
+        assert_eq!(policy.max_age_of("work/vpn"), Some(90));
+        assert_eq!(policy.max_age_of("workshop/x"), None);
+        assert_eq!(policy.max_age_of("work-email"), None);
+        assert_eq!(policy.max_age_of("home"), Some(30));
+        assert_eq!(policy.max_age_of("home/bank"), Some(30));
+        assert_eq!(policy.max_age_of("homework"), None);
+    }
+
+    #[test]
+    fn stale_passwords() {
+        let policy = RotationPolicy::new().max_age(None, Some(30));
+        let now = 100 * SECONDS_PER_DAY;
+        assert!(!policy.is_stale("bank", now - 30 * SECONDS_PER_DAY, now));
+        assert!(policy.is_stale("bank", now - 31 * SECONDS_PER_DAY, now));
+        assert!(!RotationPolicy::new().is_stale("bank", 0, now));
+        assert_eq!(age_in_days(now - 31 * SECONDS_PER_DAY - 5, now), 31);
+    }
+}
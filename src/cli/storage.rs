@@ -0,0 +1,216 @@
+mod directory;
+mod memory;
+mod records;
+mod vault_file;
+
+use super::error::RepositoryError;
+use super::name::PasswordName;
+use super::trash::TrashEntry;
+use super::vault::{VaultHeader, VaultKey};
+pub use directory::DirectoryStorage;
+pub use memory::MemoryStorage;
+use std::path::Path;
+use std::rc::Rc;
+pub use vault_file::VaultFileStorage;
+pub(crate) use vault_file::VAULT_FILE;
+
+/// Version of a password as it is stored, with its metadata unless it was
+/// written before metadata existed
+#[derive(Clone, PartialEq, Debug)]
+pub struct StoredVersion {
+    pub version: u32,
+    pub record: Vec<u8>,
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// How a repository lays out its passwords on disk
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StorageFormat {
+    /// A folder per password and a file per version
+    #[default]
+    Directory,
+    /// A single file whose index of names and versions is encrypted too
+    Vault,
+}
+
+impl StorageFormat {
+    /// Format of the repository in `root_dir`, or the default one for a
+    /// repository that doesn't exist yet
+    pub(crate) fn detect(root_dir: &Path) -> Self {
+        if root_dir.join(VAULT_FILE).is_file() {
+            StorageFormat::Vault
+        } else {
+            StorageFormat::Directory
+        }
+    }
+
+    pub(crate) fn storage(self, root_dir: &Path) -> Box<dyn Storage> {
+        match self {
+            StorageFormat::Directory => {
+                Box::new(DirectoryStorage::new(root_dir))
+            }
+            StorageFormat::Vault => Box::new(VaultFileStorage::new(root_dir)),
+        }
+    }
+}
+
+impl std::fmt::Display for StorageFormat {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            StorageFormat::Directory => write!(formatter, "directory"),
+            StorageFormat::Vault => write!(formatter, "vault"),
+        }
+    }
+}
+
+impl std::str::FromStr for StorageFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "directory" => Ok(StorageFormat::Directory),
+            "vault" => Ok(StorageFormat::Vault),
+            _ => Err(format!(
+                "unknown storage format '{format}', expected directory or vault"
+            )),
+        }
+    }
+}
+
+/// Where a repository keeps its records, given to
+/// [`PasswordRepository::with_storage`] to keep passwords somewhere else
+/// than in the formats of this crate.
+///
+/// Records, metadata and settings arrive already encrypted, the storage
+/// only decides how they are laid out. Each method should either apply its
+/// change completely or not at all. The repository takes care of locking,
+/// so methods take `&self` and storages that change in memory need interior
+/// mutability.
+///
+/// [`PasswordRepository::with_storage`]: super::PasswordRepository::with_storage
+pub trait Storage {
+    fn is_initialized(&self) -> bool;
+
+    /// Stores the header of a new vault
+    fn create(&mut self, header: &VaultHeader) -> Result<(), RepositoryError>;
+
+    fn read_header(&self) -> Result<VaultHeader, RepositoryError>;
+
+    /// Hands over the key derived from the master password, for storages
+    /// that encrypt their own layout
+    fn open(&mut self, _key: &Rc<VaultKey>) {}
+
+    /// Whether the names of the passwords can only be read once the
+    /// storage is open
+    fn encrypts_names(&self) -> bool {
+        false
+    }
+
+    /// Names of every password with at least one version, in any order
+    fn names(&self) -> Result<Vec<PasswordName>, RepositoryError>;
+
+    /// Version numbers of a password in ascending order
+    fn versions(
+        &self,
+        name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError>;
+
+    fn read_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError>;
+
+    /// Metadata of a version, missing for versions written before metadata
+    /// existed
+    fn read_metadata(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError>;
+
+    /// Stores a version with its metadata, creating the password if needed
+    fn write_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) -> Result<(), RepositoryError>;
+
+    fn delete_versions(
+        &self,
+        name: &PasswordName,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError>;
+
+    /// Deletes every version of a password, keeping the ones nested under
+    /// its name
+    fn delete_entry(&self, name: &PasswordName)
+        -> Result<(), RepositoryError>;
+
+    /// Stores every version of a password at once, replacing the versions
+    /// it had and keeping the passwords nested under its name
+    fn write_entry(
+        &self,
+        name: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError>;
+
+    /// Stores the versions of `source`, encrypted again for their new name,
+    /// under `destination` and then deletes the ones of `source`
+    fn move_entry(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.write_entry(destination, versions)?;
+        self.delete_entry(source)
+    }
+
+    /// Whether a change was interrupted by a crash, leaving records that
+    /// must be recovered before the passwords are read
+    fn interrupted(&self) -> Result<bool, RepositoryError> {
+        Ok(false)
+    }
+
+    /// Finishes or undoes the changes interrupted by a crash
+    fn recover(&self) -> Result<(), RepositoryError> {
+        Ok(())
+    }
+
+    fn read_setting(
+        &self,
+        setting: &str,
+    ) -> Result<Option<Vec<u8>>, RepositoryError>;
+
+    fn write_setting(
+        &self,
+        setting: &str,
+        contents: &[u8],
+    ) -> Result<(), RepositoryError>;
+
+    /// Moves every version of a password to the trash under the entry
+    fn trash_entry(
+        &self,
+        name: &PasswordName,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError>;
+
+    /// Passwords in the trash, in any order
+    fn trashed(&self) -> Result<Vec<TrashEntry>, RepositoryError>;
+
+    /// Moves the versions of a trash entry back to the name it was removed
+    /// from
+    fn restore_entry(&self, entry: &TrashEntry)
+        -> Result<(), RepositoryError>;
+
+    fn delete_trashed(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError>;
+}
@@ -0,0 +1,638 @@
+use super::super::atomic;
+use super::super::error::RepositoryError;
+use super::super::name::PasswordName;
+use super::super::permissions;
+use super::super::trash::TrashEntry;
+use super::super::vault::{VaultHeader, HEADER_FILE};
+use super::{Storage, StoredVersion};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of the sidecar file holding the metadata of each version
+const METADATA_EXTENSION: &str = "meta";
+
+/// Folder in the repository root holding removed passwords
+const TRASH_FOLDER: &str = ".trash";
+
+/// Prefix of the folders where files are prepared before being moved in
+/// place. Those left by a crash are finished or removed by `recover`.
+const STAGING_PREFIX: &str = ".staging-";
+
+/// File of a staging folder telling where its files go. It is written once
+/// every file is staged: a change interrupted before is undone, one
+/// interrupted after is finished.
+const JOURNAL_FILE: &str = ".journal";
+
+/// Change recorded in a staging folder
+#[derive(Serialize, Deserialize)]
+struct Journal {
+    destination: String,
+    /// Files of the destination once the change is done, each metadata
+    /// file before its version
+    files: Vec<String>,
+    /// Password whose versions are deleted once the files are in place
+    source: Option<String>,
+}
+
+/// Keeps every password in a folder named after it, with a file for each
+/// version and one for its metadata. Settings are dotfiles in the root.
+pub struct DirectoryStorage {
+    root_dir: PathBuf,
+}
+
+impl DirectoryStorage {
+    pub fn new(root_dir: &Path) -> Self {
+        DirectoryStorage {
+            root_dir: root_dir.to_path_buf(),
+        }
+    }
+
+    /// Folder holding the versions of a password. Validated names always
+    /// stay inside the repository.
+    fn password_folder(&self, password_name: &PasswordName) -> PathBuf {
+        self.root_dir.join(password_name.to_path())
+    }
+
+    fn trash_folder(&self) -> PathBuf {
+        self.root_dir.join(TRASH_FOLDER)
+    }
+
+    /// Folder in the repository root, and thus on the same file system,
+    /// where files are prepared before being renamed in place
+    fn staging_folder(&self) -> PathBuf {
+        self.root_dir
+            .join(format!("{STAGING_PREFIX}{:016x}", OsRng.next_u64()))
+    }
+
+    /// Version numbers stored in a password or trash folder, named `label`
+    /// in errors
+    fn versions_in(
+        folder: &Path,
+        label: &str,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        let mut versions = Vec::new();
+
+        let entries = match fs::read_dir(folder) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Err(RepositoryError::NotFound(label.to_string()))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            // Subfolders hold the passwords nested under this name
+            if entry.file_type()?.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            // Dotfiles are temporary files left behind by a crash
+            if path
+                .extension()
+                .is_some_and(|extension| extension == METADATA_EXTENSION)
+                || entry.file_name().to_string_lossy().starts_with('.')
+            {
+                continue;
+            }
+            let version = match path.file_name().and_then(|name| name.to_str())
+            {
+                Some(version) => version.parse::<u32>().map_err(|_| {
+                    RepositoryError::Corrupt(format!(
+                        "unexpected file {version} in {label}"
+                    ))
+                })?,
+                None => continue,
+            };
+            versions.push(version);
+        }
+        versions.sort_unstable();
+
+        Ok(versions)
+    }
+
+    fn write_version_in(
+        folder: &Path,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) -> Result<(), RepositoryError> {
+        let password_file = folder.join(version.to_string());
+        // The version only exists once its file is in place, so metadata
+        // goes first
+        atomic::write_file(
+            &password_file.with_extension(METADATA_EXTENSION),
+            metadata,
+        )?;
+        atomic::write_file(&password_file, record)?;
+        Ok(())
+    }
+
+    fn collect_names(
+        &self,
+        folder: &Path,
+        prefix: Option<&PasswordName>,
+        names: &mut Vec<PasswordName>,
+    ) -> Result<(), RepositoryError> {
+        for entry in fs::read_dir(folder)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let file_name = entry.file_name();
+            let file_name = file_name.to_str().ok_or_else(|| {
+                RepositoryError::Corrupt(format!(
+                    "invalid password name {}",
+                    file_name.to_string_lossy()
+                ))
+            })?;
+            // Dotfiles are never listed as passwords, so the lock, settings,
+            // trash and staging folders can share the root with them
+            if file_name.starts_with('.') {
+                continue;
+            }
+
+            let name = match prefix {
+                Some(prefix) => prefix.join(file_name),
+                None => PasswordName::new(file_name),
+            }
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+            if !self.versions(&name)?.is_empty() {
+                names.push(name.clone());
+            }
+            self.collect_names(&entry.path(), Some(&name), names)?;
+        }
+        Ok(())
+    }
+
+    fn remove_versions(
+        password_folder: &Path,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError> {
+        for version in versions {
+            let password_file = password_folder.join(version.to_string());
+            match fs::remove_file(
+                password_file.with_extension(METADATA_EXTENSION),
+            ) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                    return Err(error.into())
+                }
+                _ => (),
+            }
+            fs::remove_file(password_file)?;
+        }
+        atomic::sync_folder(password_folder)?;
+        Ok(())
+    }
+
+    fn copy_versions(
+        &self,
+        source: &PasswordName,
+        versions: &[u32],
+        folder: &Path,
+    ) -> Result<(), RepositoryError> {
+        let source_folder = self.password_folder(source);
+        permissions::create_dir(folder)?;
+        for version in versions {
+            let file_name = PathBuf::from(version.to_string());
+            let metadata_name = file_name.with_extension(METADATA_EXTENSION);
+            match atomic::copy_file(
+                &source_folder.join(&metadata_name),
+                &folder.join(&metadata_name),
+            ) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                    return Err(error.into())
+                }
+                _ => (),
+            }
+            atomic::copy_file(
+                &source_folder.join(&file_name),
+                &folder.join(&file_name),
+            )?;
+        }
+        atomic::sync_folder(folder)?;
+        Ok(())
+    }
+
+    fn stage_versions(
+        versions: &[StoredVersion],
+        staging: &Path,
+    ) -> Result<(), RepositoryError> {
+        permissions::create_dir(staging)?;
+        for stored in versions {
+            let password_file = staging.join(stored.version.to_string());
+            if let Some(metadata) = &stored.metadata {
+                atomic::write_file(
+                    &password_file.with_extension(METADATA_EXTENSION),
+                    metadata,
+                )?;
+            }
+            atomic::write_file(&password_file, &stored.record)?;
+        }
+        Ok(())
+    }
+
+    /// Stages the versions and their journal, then applies it. A failure
+    /// before the journal is written removes the staging folder, one after
+    /// leaves it to `recover`.
+    fn replace_entry(
+        &self,
+        destination: &PasswordName,
+        versions: &[StoredVersion],
+        source: Option<&PasswordName>,
+    ) -> Result<(), RepositoryError> {
+        let mut files = Vec::new();
+        for stored in versions {
+            if stored.metadata.is_some() {
+                files.push(format!("{}.{METADATA_EXTENSION}", stored.version));
+            }
+            files.push(stored.version.to_string());
+        }
+        let journal = Journal {
+            destination: destination.to_string(),
+            files,
+            source: source.map(ToString::to_string),
+        };
+
+        let staging = self.staging_folder();
+        let staged = Self::stage_versions(versions, &staging).and_then(|()| {
+            let serialized =
+                serde_json::to_vec(&journal).map_err(|error| {
+                    RepositoryError::Corrupt(error.to_string())
+                })?;
+            atomic::write_file(&staging.join(JOURNAL_FILE), &serialized)?;
+            Ok(())
+        });
+        if let Err(error) = staged {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(error);
+        }
+        self.apply(&staging, &journal)
+    }
+
+    /// Moves the files of a staging folder in place, deleting the versions
+    /// the destination had and then the source. Every step can be repeated,
+    /// so applying the journal again finishes an interrupted change.
+    fn apply(
+        &self,
+        staging: &Path,
+        journal: &Journal,
+    ) -> Result<(), RepositoryError> {
+        let destination_folder =
+            self.password_folder(&journal_name(&journal.destination)?);
+        if let Some(file_name) =
+            journal.files.iter().find(|file| !is_version_file(file))
+        {
+            return Err(RepositoryError::Corrupt(format!(
+                "unexpected file {file_name} in journal"
+            )));
+        }
+        permissions::create_dir_all(&destination_folder)?;
+        for entry in fs::read_dir(&destination_folder)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_file()
+                && is_version_file(&file_name)
+                && !journal.files.contains(&file_name)
+            {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        for file_name in &journal.files {
+            let staged = staging.join(file_name);
+            if staged.exists() {
+                fs::rename(&staged, destination_folder.join(file_name))?;
+            }
+        }
+        atomic::sync_folder(&destination_folder)?;
+
+        if let Some(source) = &journal.source {
+            match self.delete_entry(&journal_name(source)?) {
+                Err(RepositoryError::NotFound(_)) => (),
+                result => result?,
+            }
+        }
+        fs::remove_dir_all(staging)?;
+        Ok(())
+    }
+
+    /// Staging folders in the repository root
+    fn staging_folders(&self) -> Result<Vec<PathBuf>, RepositoryError> {
+        let entries = match fs::read_dir(&self.root_dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(error) => return Err(error.into()),
+        };
+        let mut folders = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir()
+                && entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(STAGING_PREFIX)
+            {
+                folders.push(entry.path());
+            }
+        }
+        Ok(folders)
+    }
+
+    /// Removes `folder` and its parents while they are empty, up to the
+    /// repository root
+    fn remove_empty_folders(
+        &self,
+        folder: &Path,
+    ) -> Result<(), RepositoryError> {
+        let mut folder = folder;
+        while folder != self.root_dir && fs::read_dir(folder)?.next().is_none()
+        {
+            fs::remove_dir(folder)?;
+            folder = match folder.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        Ok(())
+    }
+
+    fn trash_file(&self, entry: &TrashEntry) -> PathBuf {
+        self.trash_folder().join(format!("{}.json", entry.id()))
+    }
+}
+
+impl Storage for DirectoryStorage {
+    fn is_initialized(&self) -> bool {
+        self.root_dir.join(HEADER_FILE).exists()
+    }
+
+    fn create(&mut self, header: &VaultHeader) -> Result<(), RepositoryError> {
+        header.write(self.root_dir.join(HEADER_FILE))
+    }
+
+    fn read_header(&self) -> Result<VaultHeader, RepositoryError> {
+        VaultHeader::read(self.root_dir.join(HEADER_FILE))
+    }
+
+    fn names(&self) -> Result<Vec<PasswordName>, RepositoryError> {
+        let mut names = Vec::new();
+        self.collect_names(&self.root_dir, None, &mut names)?;
+        Ok(names)
+    }
+
+    fn versions(
+        &self,
+        name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        Self::versions_in(&self.password_folder(name), name.as_str())
+    }
+
+    fn read_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        read_optional(&self.password_folder(name).join(version.to_string()))
+    }
+
+    fn read_metadata(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        read_optional(
+            &self
+                .password_folder(name)
+                .join(format!("{version}.{METADATA_EXTENSION}")),
+        )
+    }
+
+    fn write_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) -> Result<(), RepositoryError> {
+        let password_folder = self.password_folder(name);
+        if password_folder.exists() {
+            return Self::write_version_in(
+                &password_folder,
+                version,
+                record,
+                metadata,
+            );
+        }
+
+        // A new folder is written aside and renamed in place with its first
+        // version, so that a failure never leaves an entry without versions
+        let staging = self.staging_folder();
+        let result = permissions::create_dir(&staging)
+            .map_err(RepositoryError::from)
+            .and_then(|()| {
+                Self::write_version_in(&staging, version, record, metadata)
+            })
+            .and_then(|()| {
+                if let Some(parent) = password_folder.parent() {
+                    permissions::create_dir_all(parent)?;
+                }
+                atomic::rename(&staging, &password_folder)?;
+                Ok(())
+            });
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&staging);
+        }
+        result
+    }
+
+    fn delete_versions(
+        &self,
+        name: &PasswordName,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError> {
+        Self::remove_versions(&self.password_folder(name), versions)
+    }
+
+    /// Also removes the folders left empty
+    fn delete_entry(
+        &self,
+        name: &PasswordName,
+    ) -> Result<(), RepositoryError> {
+        let password_folder = self.password_folder(name);
+        Self::remove_versions(&password_folder, &self.versions(name)?)?;
+        self.remove_empty_folders(&password_folder)
+    }
+
+    fn write_entry(
+        &self,
+        name: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.replace_entry(name, versions, None)
+    }
+
+    /// The source is deleted by the journal too, so that a crash never
+    /// leaves the password under both names
+    fn move_entry(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.replace_entry(destination, versions, Some(source))
+    }
+
+    fn interrupted(&self) -> Result<bool, RepositoryError> {
+        Ok(!self.staging_folders()?.is_empty())
+    }
+
+    fn recover(&self) -> Result<(), RepositoryError> {
+        for staging in self.staging_folders()? {
+            match read_optional(&staging.join(JOURNAL_FILE))? {
+                Some(contents) => {
+                    let journal: Journal = serde_json::from_slice(&contents)
+                        .map_err(|error| {
+                        RepositoryError::Corrupt(error.to_string())
+                    })?;
+                    self.apply(&staging, &journal)?;
+                }
+                None => fs::remove_dir_all(&staging)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_setting(
+        &self,
+        setting: &str,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        read_optional(&self.root_dir.join(format!(".{setting}")))
+    }
+
+    fn write_setting(
+        &self,
+        setting: &str,
+        contents: &[u8],
+    ) -> Result<(), RepositoryError> {
+        atomic::write_file(
+            &self.root_dir.join(format!(".{setting}")),
+            contents,
+        )?;
+        Ok(())
+    }
+
+    fn trash_entry(
+        &self,
+        name: &PasswordName,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        let trash_folder = self.trash_folder();
+        permissions::create_dir_all(&trash_folder)?;
+
+        // Versions are copied before being deleted, so that a failure
+        // never loses them
+        self.copy_versions(
+            name,
+            &self.versions(name)?,
+            &trash_folder.join(entry.id()),
+        )?;
+        let serialized = serde_json::to_vec(entry)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+        atomic::write_file(&self.trash_file(entry), &serialized)?;
+        self.delete_entry(name)
+    }
+
+    fn trashed(&self) -> Result<Vec<TrashEntry>, RepositoryError> {
+        let entries = match fs::read_dir(self.trash_folder()) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Vec::new())
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut trash = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            let Some(id) = path
+                .file_stem()
+                .filter(|_| path.extension().is_some_and(|ext| ext == "json"))
+                .and_then(|id| id.to_str())
+            else {
+                continue;
+            };
+            let entry: TrashEntry = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|error| {
+                    RepositoryError::Corrupt(format!(
+                        "trash entry {id}: {error}"
+                    ))
+                })?;
+            trash.push(entry.with_id(id.to_string()));
+        }
+        Ok(trash)
+    }
+
+    fn restore_entry(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        let name = PasswordName::new(entry.name())?;
+        let entry_folder = self.trash_folder().join(entry.id());
+        let mut versions = Vec::new();
+        for version in Self::versions_in(&entry_folder, entry.name())? {
+            let password_file = entry_folder.join(version.to_string());
+            versions.push(StoredVersion {
+                version,
+                record: fs::read(&password_file)?,
+                metadata: read_optional(
+                    &password_file.with_extension(METADATA_EXTENSION),
+                )?,
+            });
+        }
+        self.replace_entry(&name, &versions, None)?;
+        fs::remove_dir_all(&entry_folder)?;
+        fs::remove_file(self.trash_file(entry))?;
+        Ok(())
+    }
+
+    fn delete_trashed(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        match fs::remove_dir_all(self.trash_folder().join(entry.id())) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                return Err(error.into())
+            }
+            _ => (),
+        }
+        fs::remove_file(self.trash_file(entry))?;
+        Ok(())
+    }
+}
+
+fn journal_name(name: &str) -> Result<PasswordName, RepositoryError> {
+    PasswordName::new(name)
+        .map_err(|error| RepositoryError::Corrupt(error.to_string()))
+}
+
+/// Whether a file of a password folder holds a version or its metadata
+fn is_version_file(file_name: &str) -> bool {
+    let version = file_name
+        .strip_suffix(&format!(".{METADATA_EXTENSION}"))
+        .unwrap_or(file_name);
+    !version.is_empty() && version.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, RepositoryError> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
@@ -0,0 +1,154 @@
+use super::super::error::RepositoryError;
+use super::super::name::PasswordName;
+use super::super::trash::TrashEntry;
+use super::super::vault::VaultHeader;
+use super::records::Records;
+use super::{Storage, StoredVersion};
+use std::cell::RefCell;
+
+/// Keeps every record in memory, for tests and for programs that load and
+/// save passwords on their own. Nothing outlives the storage.
+#[derive(Default)]
+pub struct MemoryStorage {
+    header: Option<VaultHeader>,
+    records: RefCell<Records>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn is_initialized(&self) -> bool {
+        self.header.is_some()
+    }
+
+    fn create(&mut self, header: &VaultHeader) -> Result<(), RepositoryError> {
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    fn read_header(&self) -> Result<VaultHeader, RepositoryError> {
+        self.header.clone().ok_or(RepositoryError::NotInitialized)
+    }
+
+    fn names(&self) -> Result<Vec<PasswordName>, RepositoryError> {
+        self.records.borrow().names()
+    }
+
+    fn versions(
+        &self,
+        name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        self.records.borrow().versions(name)
+    }
+
+    fn read_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        Ok(self.records.borrow().read_version(name, version))
+    }
+
+    fn read_metadata(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        Ok(self.records.borrow().read_metadata(name, version))
+    }
+
+    fn write_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) -> Result<(), RepositoryError> {
+        self.records
+            .borrow_mut()
+            .write_version(name, version, record, metadata);
+        Ok(())
+    }
+
+    fn delete_versions(
+        &self,
+        name: &PasswordName,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().delete_versions(name, versions)
+    }
+
+    fn delete_entry(
+        &self,
+        name: &PasswordName,
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().delete_entry(name)
+    }
+
+    fn write_entry(
+        &self,
+        name: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().write_entry(name, versions);
+        Ok(())
+    }
+
+    fn move_entry(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        let mut records = self.records.borrow_mut();
+        records.delete_entry(source)?;
+        records.write_entry(destination, versions);
+        Ok(())
+    }
+
+    fn read_setting(
+        &self,
+        setting: &str,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        Ok(self.records.borrow().read_setting(setting))
+    }
+
+    fn write_setting(
+        &self,
+        setting: &str,
+        contents: &[u8],
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().write_setting(setting, contents);
+        Ok(())
+    }
+
+    fn trash_entry(
+        &self,
+        name: &PasswordName,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().trash_entry(name, entry)
+    }
+
+    fn trashed(&self) -> Result<Vec<TrashEntry>, RepositoryError> {
+        Ok(self.records.borrow().trashed())
+    }
+
+    fn restore_entry(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().restore_entry(entry)
+    }
+
+    fn delete_trashed(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.records.borrow_mut().delete_trashed(entry)
+    }
+}
@@ -0,0 +1,261 @@
+use super::super::error::RepositoryError;
+use super::super::name::PasswordName;
+use super::super::trash::TrashEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Every password, setting and trash entry of a repository, each holding
+/// a blob of type `B`: the encrypted contents themselves once loaded, or
+/// where to find them in a vault file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Records<B = Vec<u8>> {
+    passwords: BTreeMap<String, Versions<B>>,
+    settings: BTreeMap<String, B>,
+    /// Removed passwords by trash entry id
+    trash: BTreeMap<String, Trashed<B>>,
+}
+
+type Versions<B> = BTreeMap<u32, StoredVersion<B>>;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct StoredVersion<B> {
+    record: B,
+    metadata: Option<B>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct Trashed<B> {
+    entry: TrashEntry,
+    versions: Versions<B>,
+}
+
+impl<B> Default for Records<B> {
+    fn default() -> Self {
+        Records {
+            passwords: BTreeMap::new(),
+            settings: BTreeMap::new(),
+            trash: BTreeMap::new(),
+        }
+    }
+}
+
+impl<B> Records<B> {
+    /// Replaces every blob, in a fixed order, stopping at the first error
+    pub fn try_map<C, E>(
+        self,
+        mut map: impl FnMut(B) -> Result<C, E>,
+    ) -> Result<Records<C>, E> {
+        let mut map_versions = |versions: Versions<B>| {
+            versions
+                .into_iter()
+                .map(|(version, stored)| {
+                    let stored = StoredVersion {
+                        record: map(stored.record)?,
+                        metadata: stored.metadata.map(&mut map).transpose()?,
+                    };
+                    Ok((version, stored))
+                })
+                .collect::<Result<Versions<C>, E>>()
+        };
+
+        let mut passwords = BTreeMap::new();
+        for (name, versions) in self.passwords {
+            passwords.insert(name, map_versions(versions)?);
+        }
+        let mut trash = BTreeMap::new();
+        for (id, trashed) in self.trash {
+            let trashed = Trashed {
+                entry: trashed.entry,
+                versions: map_versions(trashed.versions)?,
+            };
+            trash.insert(id, trashed);
+        }
+        let mut settings = BTreeMap::new();
+        for (setting, contents) in self.settings {
+            settings.insert(setting, map(contents)?);
+        }
+
+        Ok(Records {
+            passwords,
+            settings,
+            trash,
+        })
+    }
+}
+
+impl Records {
+    pub fn names(&self) -> Result<Vec<PasswordName>, RepositoryError> {
+        self.passwords
+            .keys()
+            .map(|name| {
+                PasswordName::new(name.as_str()).map_err(|error| {
+                    RepositoryError::Corrupt(error.to_string())
+                })
+            })
+            .collect()
+    }
+
+    pub fn versions(
+        &self,
+        name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        Ok(self.password(name)?.keys().copied().collect())
+    }
+
+    pub fn read_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Option<Vec<u8>> {
+        let stored = self.passwords.get(name.as_str())?.get(&version)?;
+        Some(stored.record.clone())
+    }
+
+    pub fn read_metadata(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Option<Vec<u8>> {
+        let stored = self.passwords.get(name.as_str())?.get(&version)?;
+        stored.metadata.clone()
+    }
+
+    pub fn write_version(
+        &mut self,
+        name: &PasswordName,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) {
+        let stored = StoredVersion {
+            record: record.to_vec(),
+            metadata: Some(metadata.to_vec()),
+        };
+        self.passwords
+            .entry(name.to_string())
+            .or_default()
+            .insert(version, stored);
+    }
+
+    pub fn delete_versions(
+        &mut self,
+        name: &PasswordName,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError> {
+        let stored = self.password_mut(name)?;
+        for version in versions {
+            stored.remove(version);
+        }
+        if stored.is_empty() {
+            self.passwords.remove(name.as_str());
+        }
+        Ok(())
+    }
+
+    pub fn delete_entry(
+        &mut self,
+        name: &PasswordName,
+    ) -> Result<(), RepositoryError> {
+        self.take_password(name).map(drop)
+    }
+
+    pub fn write_entry(
+        &mut self,
+        name: &PasswordName,
+        versions: &[super::StoredVersion],
+    ) {
+        let versions = versions
+            .iter()
+            .map(|stored| {
+                let blobs = StoredVersion {
+                    record: stored.record.clone(),
+                    metadata: stored.metadata.clone(),
+                };
+                (stored.version, blobs)
+            })
+            .collect();
+        self.passwords.insert(name.to_string(), versions);
+    }
+
+    pub fn read_setting(&self, setting: &str) -> Option<Vec<u8>> {
+        self.settings.get(setting).cloned()
+    }
+
+    pub fn write_setting(&mut self, setting: &str, contents: &[u8]) {
+        self.settings.insert(setting.to_string(), contents.to_vec());
+    }
+
+    pub fn trash_entry(
+        &mut self,
+        name: &PasswordName,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        let versions = self.take_password(name)?;
+        let trashed = Trashed {
+            entry: entry.clone(),
+            versions,
+        };
+        self.trash.insert(entry.id().to_string(), trashed);
+        Ok(())
+    }
+
+    pub fn trashed(&self) -> Vec<TrashEntry> {
+        self.trash
+            .iter()
+            .map(|(id, trashed)| trashed.entry.clone().with_id(id.clone()))
+            .collect()
+    }
+
+    pub fn restore_entry(
+        &mut self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        let trashed = self.take_trashed(entry)?;
+        self.passwords
+            .insert(entry.name().to_string(), trashed.versions);
+        Ok(())
+    }
+
+    pub fn delete_trashed(
+        &mut self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.take_trashed(entry).map(drop)
+    }
+
+    fn password(
+        &self,
+        name: &PasswordName,
+    ) -> Result<&Versions<Vec<u8>>, RepositoryError> {
+        self.passwords
+            .get(name.as_str())
+            .ok_or_else(|| RepositoryError::NotFound(name.to_string()))
+    }
+
+    fn password_mut(
+        &mut self,
+        name: &PasswordName,
+    ) -> Result<&mut Versions<Vec<u8>>, RepositoryError> {
+        self.passwords
+            .get_mut(name.as_str())
+            .ok_or_else(|| RepositoryError::NotFound(name.to_string()))
+    }
+
+    fn take_password(
+        &mut self,
+        name: &PasswordName,
+    ) -> Result<Versions<Vec<u8>>, RepositoryError> {
+        self.passwords
+            .remove(name.as_str())
+            .ok_or_else(|| RepositoryError::NotFound(name.to_string()))
+    }
+
+    fn take_trashed(
+        &mut self,
+        entry: &TrashEntry,
+    ) -> Result<Trashed<Vec<u8>>, RepositoryError> {
+        self.trash
+            .remove(entry.id())
+            .ok_or_else(|| RepositoryError::NotFound(entry.name().to_string()))
+    }
+}
@@ -0,0 +1,421 @@
+use super::super::atomic;
+use super::super::error::RepositoryError;
+use super::super::name::PasswordName;
+use super::super::trash::TrashEntry;
+use super::super::vault::{AssociatedData, VaultHeader, VaultKey};
+use super::records::Records;
+use super::{Storage, StoredVersion};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Name of the file, inside the repository root, holding a whole vault
+pub const VAULT_FILE: &str = "passwords.vault";
+
+/// First bytes of every vault file
+const MAGIC: &[u8; 8] = b"PWMVAULT";
+
+/// Version of the layout below, bumped on incompatible changes
+const FORMAT_VERSION: u32 = 1;
+
+/// Where a blob is found in the records section of a vault file
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+struct Span {
+    offset: u64,
+    length: u64,
+}
+
+/// Keeps the whole repository in one file, laid out as
+///
+/// ```text
+/// magic | format | header length | header | index length | index | records
+/// ```
+///
+/// with the format and lengths as little-endian 32 bit integers. The
+/// header is the JSON vault header, readable without the master password.
+/// The index maps names, versions, settings and trash entries to spans of
+/// the records section and is encrypted as a whole, so that not even the
+/// names of the passwords are revealed. Records are stored as they come,
+/// already encrypted. Every change rewrites the file atomically.
+pub struct VaultFileStorage {
+    path: PathBuf,
+    key: Option<Rc<VaultKey>>,
+}
+
+impl VaultFileStorage {
+    pub fn new(root_dir: &Path) -> Self {
+        VaultFileStorage {
+            path: root_dir.join(VAULT_FILE),
+            key: None,
+        }
+    }
+
+    fn key(&self) -> Result<&VaultKey, RepositoryError> {
+        self.key.as_deref().ok_or(RepositoryError::Locked)
+    }
+
+    fn read_file(&self) -> Result<Vec<u8>, RepositoryError> {
+        match fs::read(&self.path) {
+            Ok(contents) => Ok(contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Err(RepositoryError::NotInitialized)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn load(&self) -> Result<(VaultHeader, Records), RepositoryError> {
+        let contents = self.read_file()?;
+        let mut reader = Reader::new(&contents)?;
+        let header = VaultHeader::from_json(reader.section()?)?;
+        let index = self
+            .key()?
+            .decrypt(reader.section()?, AssociatedData::Index)?;
+        let records = reader.rest();
+
+        let index: Records<Span> = serde_json::from_slice(&index)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+        let records = index.try_map(|span| {
+            usize::try_from(span.offset)
+                .ok()
+                .zip(usize::try_from(span.length).ok())
+                .and_then(|(offset, length)| {
+                    records.get(offset..offset.checked_add(length)?)
+                })
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| {
+                    RepositoryError::Corrupt(
+                        "vault index points past its records".into(),
+                    )
+                })
+        })?;
+        Ok((header, records))
+    }
+
+    fn save(
+        &self,
+        header: &VaultHeader,
+        records: Records,
+    ) -> Result<(), RepositoryError> {
+        let mut blobs = Vec::new();
+        let index = records.try_map(|blob| {
+            let span = Span {
+                offset: blobs.len() as u64,
+                length: blob.len() as u64,
+            };
+            blobs.extend(blob);
+            Ok::<_, RepositoryError>(span)
+        })?;
+        let index = serde_json::to_vec(&index)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+
+        let mut contents = MAGIC.to_vec();
+        contents.extend(FORMAT_VERSION.to_le_bytes());
+        write_section(&mut contents, &header.to_json()?)?;
+        write_section(
+            &mut contents,
+            &self.key()?.encrypt(&index, AssociatedData::Index),
+        )?;
+        contents.extend(blobs);
+        atomic::write_file(&self.path, &contents)?;
+        Ok(())
+    }
+
+    fn read<T>(
+        &self,
+        read: impl FnOnce(&Records) -> Result<T, RepositoryError>,
+    ) -> Result<T, RepositoryError> {
+        let (_, records) = self.load()?;
+        read(&records)
+    }
+
+    fn change(
+        &self,
+        change: impl FnOnce(&mut Records) -> Result<(), RepositoryError>,
+    ) -> Result<(), RepositoryError> {
+        let (header, mut records) = self.load()?;
+        change(&mut records)?;
+        self.save(&header, records)
+    }
+}
+
+impl Storage for VaultFileStorage {
+    fn is_initialized(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn create(&mut self, header: &VaultHeader) -> Result<(), RepositoryError> {
+        self.save(header, Records::default())
+    }
+
+    fn read_header(&self) -> Result<VaultHeader, RepositoryError> {
+        let contents = self.read_file()?;
+        VaultHeader::from_json(Reader::new(&contents)?.section()?)
+    }
+
+    fn open(&mut self, key: &Rc<VaultKey>) {
+        self.key = Some(Rc::clone(key));
+    }
+
+    fn encrypts_names(&self) -> bool {
+        true
+    }
+
+    fn names(&self) -> Result<Vec<PasswordName>, RepositoryError> {
+        self.read(Records::names)
+    }
+
+    fn versions(
+        &self,
+        name: &PasswordName,
+    ) -> Result<Vec<u32>, RepositoryError> {
+        self.read(|records| records.versions(name))
+    }
+
+    fn read_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        self.read(|records| Ok(records.read_version(name, version)))
+    }
+
+    fn read_metadata(
+        &self,
+        name: &PasswordName,
+        version: u32,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        self.read(|records| Ok(records.read_metadata(name, version)))
+    }
+
+    fn write_version(
+        &self,
+        name: &PasswordName,
+        version: u32,
+        record: &[u8],
+        metadata: &[u8],
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| {
+            records.write_version(name, version, record, metadata);
+            Ok(())
+        })
+    }
+
+    fn delete_versions(
+        &self,
+        name: &PasswordName,
+        versions: &[u32],
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| records.delete_versions(name, versions))
+    }
+
+    fn delete_entry(
+        &self,
+        name: &PasswordName,
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| records.delete_entry(name))
+    }
+
+    fn write_entry(
+        &self,
+        name: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| {
+            records.write_entry(name, versions);
+            Ok(())
+        })
+    }
+
+    /// Both names change in the same rewrite of the file
+    fn move_entry(
+        &self,
+        source: &PasswordName,
+        destination: &PasswordName,
+        versions: &[StoredVersion],
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| {
+            records.delete_entry(source)?;
+            records.write_entry(destination, versions);
+            Ok(())
+        })
+    }
+
+    fn read_setting(
+        &self,
+        setting: &str,
+    ) -> Result<Option<Vec<u8>>, RepositoryError> {
+        self.read(|records| Ok(records.read_setting(setting)))
+    }
+
+    fn write_setting(
+        &self,
+        setting: &str,
+        contents: &[u8],
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| {
+            records.write_setting(setting, contents);
+            Ok(())
+        })
+    }
+
+    fn trash_entry(
+        &self,
+        name: &PasswordName,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| records.trash_entry(name, entry))
+    }
+
+    fn trashed(&self) -> Result<Vec<TrashEntry>, RepositoryError> {
+        self.read(|records| Ok(records.trashed()))
+    }
+
+    fn restore_entry(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| records.restore_entry(entry))
+    }
+
+    fn delete_trashed(
+        &self,
+        entry: &TrashEntry,
+    ) -> Result<(), RepositoryError> {
+        self.change(|records| records.delete_trashed(entry))
+    }
+}
+
+fn write_section(
+    contents: &mut Vec<u8>,
+    section: &[u8],
+) -> Result<(), RepositoryError> {
+    let length = u32::try_from(section.len()).map_err(|_| {
+        RepositoryError::Corrupt("vault section is too large".into())
+    })?;
+    contents.extend(length.to_le_bytes());
+    contents.extend(section);
+    Ok(())
+}
+
+/// Walks through the sections of a vault file
+struct Reader<'a> {
+    contents: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the magic bytes and the format version
+    fn new(contents: &'a [u8]) -> Result<Self, RepositoryError> {
+        let Some(contents) = contents.strip_prefix(MAGIC) else {
+            return Err(RepositoryError::Corrupt("not a vault file".into()));
+        };
+        let mut reader = Reader { contents };
+        let format = reader.number()?;
+        if format != FORMAT_VERSION {
+            return Err(RepositoryError::Corrupt(format!(
+                "unsupported vault file format {format}"
+            )));
+        }
+        Ok(reader)
+    }
+
+    fn number(&mut self) -> Result<u32, RepositoryError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn section(&mut self) -> Result<&'a [u8], RepositoryError> {
+        let length = self.number()?;
+        self.take(length as usize)
+    }
+
+    fn rest(self) -> &'a [u8] {
+        self.contents
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], RepositoryError> {
+        if self.contents.len() < length {
+            return Err(RepositoryError::Corrupt(
+                "truncated vault file".into(),
+            ));
+        }
+        let (taken, rest) = self.contents.split_at(length);
+        self.contents = rest;
+        Ok(taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER_PASSWORD: &str = "correct horse battery staple";
+
+    fn storage(root: &Path) -> (VaultHeader, VaultFileStorage) {
+        let (header, key) = VaultHeader::create(MASTER_PASSWORD).unwrap();
+        let mut storage = VaultFileStorage::new(root);
+        storage.open(&Rc::new(key));
+        storage.create(&header).unwrap();
+        (header, storage)
+    }
+
+    #[test]
+    fn records_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        let (header, storage) = storage(root.path());
+        let name = PasswordName::new("work/bank").unwrap();
+        storage.write_version(&name, 1, b"first", b"{}").unwrap();
+        storage.write_version(&name, 2, b"second", b"{}").unwrap();
+        storage.write_setting("rotation", b"policy").unwrap();
+
+        assert_eq!(storage.read_header().unwrap(), header);
+        assert_eq!(storage.names().unwrap(), std::slice::from_ref(&name));
+        assert_eq!(storage.versions(&name).unwrap(), [1, 2]);
+        assert_eq!(
+            storage.read_version(&name, 2).unwrap().as_deref(),
+            Some(&b"second"[..])
+        );
+        assert_eq!(storage.read_version(&name, 3).unwrap(), None);
+        assert_eq!(
+            storage.read_setting("rotation").unwrap().as_deref(),
+            Some(&b"policy"[..])
+        );
+    }
+
+    #[test]
+    fn names_are_encrypted() {
+        let root = tempfile::tempdir().unwrap();
+        let (_, storage) = storage(root.path());
+        let name = PasswordName::new("secret-bank").unwrap();
+        storage.write_version(&name, 1, b"value", b"{}").unwrap();
+
+        let contents = fs::read(root.path().join(VAULT_FILE)).unwrap();
+        assert!(contents.starts_with(MAGIC));
+        assert!(!contents.windows(11).any(|window| window == b"secret-bank"));
+
+        let locked = VaultFileStorage::new(root.path());
+        assert!(matches!(locked.names(), Err(RepositoryError::Locked)));
+    }
+
+    #[test]
+    fn damaged_files_are_corrupt() {
+        let root = tempfile::tempdir().unwrap();
+        let (_, storage) = storage(root.path());
+        let path = root.path().join(VAULT_FILE);
+        let contents = fs::read(&path).unwrap();
+
+        for damaged in [
+            b"PWMVAULX".to_vec(),
+            contents[..contents.len() - 1].to_vec(),
+            [MAGIC.to_vec(), 2u32.to_le_bytes().to_vec()].concat(),
+        ] {
+            fs::write(&path, damaged).unwrap();
+            assert!(matches!(
+                storage.names(),
+                Err(RepositoryError::Corrupt(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,251 @@
+mod matching;
+
+use matching::{cardinality, find_matches, Pattern};
+
+/// Guesses per second of an offline attack against a slow password hash
+const GUESSES_PER_SECOND: f64 = 1e4;
+
+/// Passwords shorter than this are reported as short
+const SHORT_LENGTH: usize = 8;
+
+/// Reason that makes a password easier to guess
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Weakness {
+    CommonPassword,
+    DictionaryWord,
+    KeyboardWalk,
+    Repeat,
+    Sequence,
+    Date,
+    Short,
+}
+
+impl From<Pattern> for Weakness {
+    fn from(pattern: Pattern) -> Self {
+        match pattern {
+            Pattern::CommonPassword => Weakness::CommonPassword,
+            Pattern::DictionaryWord => Weakness::DictionaryWord,
+            Pattern::KeyboardWalk => Weakness::KeyboardWalk,
+            Pattern::Repeat => Weakness::Repeat,
+            Pattern::Sequence => Weakness::Sequence,
+            Pattern::Date => Weakness::Date,
+        }
+    }
+}
+
+impl std::fmt::Display for Weakness {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let warning = match self {
+            Weakness::CommonPassword => "it is one of the most used passwords",
+            Weakness::DictionaryWord => "it contains dictionary words",
+            Weakness::KeyboardWalk => "it contains a keyboard pattern",
+            Weakness::Repeat => "it contains repeated characters",
+            Weakness::Sequence => "it contains a sequence like abc or 6543",
+            Weakness::Date => "it contains a date or a year",
+            Weakness::Short => "it is short",
+        };
+        write!(formatter, "{warning}")
+    }
+}
+
+/// Estimation of how hard a password is to guess, in the spirit of
+/// zxcvbn: the password is split into the guessable patterns that need the
+/// fewest guesses, and the remaining characters are brute forced
+#[derive(Clone, PartialEq, Debug)]
+pub struct Strength {
+    /// Base 2 logarithm of the number of guesses
+    entropy_bits: f64,
+    weaknesses: Vec<Weakness>,
+}
+
+impl Strength {
+    pub fn estimate(password: &str) -> Self {
+        let characters: Vec<char> = password.chars().collect();
+        let (entropy_bits, patterns) = cheapest_split(&characters);
+
+        let mut weaknesses = Vec::new();
+        for weakness in patterns.into_iter().map(Weakness::from) {
+            if !weaknesses.contains(&weakness) {
+                weaknesses.push(weakness);
+            }
+        }
+        if characters.len() < SHORT_LENGTH {
+            weaknesses.push(Weakness::Short);
+        }
+
+        Strength {
+            entropy_bits,
+            weaknesses,
+        }
+    }
+
+    pub fn entropy_bits(&self) -> f64 {
+        self.entropy_bits
+    }
+
+    pub fn guesses(&self) -> f64 {
+        self.entropy_bits.exp2()
+    }
+
+    /// Score from 0, guessed almost instantly, to 4, out of reach
+    pub fn score(&self) -> u8 {
+        match self.guesses().log10() {
+            guesses if guesses < 3.0 => 0,
+            guesses if guesses < 6.0 => 1,
+            guesses if guesses < 8.0 => 2,
+            guesses if guesses < 10.0 => 3,
+            _ => 4,
+        }
+    }
+
+    /// Passwords with a score below 3 are considered weak
+    pub fn is_weak(&self) -> bool {
+        self.score() < 3
+    }
+
+    pub fn weaknesses(&self) -> &[Weakness] {
+        &self.weaknesses
+    }
+
+    /// Time an offline attack against a slow hash needs to guess the
+    /// password
+    pub fn crack_time(&self) -> String {
+        format_duration(self.guesses() / GUESSES_PER_SECOND)
+    }
+}
+
+impl std::fmt::Display for Strength {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        let rating = ["very weak", "weak", "fair", "strong", "very strong"];
+        writeln!(
+            formatter,
+            "Score: {}/4 ({})",
+            self.score(),
+            rating[self.score() as usize]
+        )?;
+        writeln!(formatter, "Entropy: {:.1} bits", self.entropy_bits)?;
+        write!(formatter, "Crack time: {}", self.crack_time())?;
+        // Strong passwords may contain a pattern by chance, reporting it
+        // would only be noise
+        if !self.is_weak() {
+            return Ok(());
+        }
+        for weakness in &self.weaknesses {
+            write!(formatter, "\nWarning: {weakness}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Fewest guesses needed for the password, as a base 2 logarithm, and the
+/// patterns used to reach them
+fn cheapest_split(characters: &[char]) -> (f64, Vec<Pattern>) {
+    let matches = find_matches(characters);
+    let brute_force = cardinality(characters).max(1.0).log2();
+
+    // Cheapest guesses for every prefix and the match that ends it, if any
+    let mut cheapest = vec![(0.0, None); characters.len() + 1];
+    for end in 1..=characters.len() {
+        cheapest[end] = (cheapest[end - 1].0 + brute_force, None);
+        for (index, found) in matches.iter().enumerate() {
+            let bits = cheapest[found.start].0 + found.guesses.log2();
+            if found.end == end && bits < cheapest[end].0 {
+                cheapest[end] = (bits, Some(index));
+            }
+        }
+    }
+
+    let mut patterns = Vec::new();
+    let mut end = characters.len();
+    while end > 0 {
+        match cheapest[end].1 {
+            Some(index) => {
+                patterns.push(matches[index].pattern);
+                end = matches[index].start;
+            }
+            None => end -= 1,
+        }
+    }
+    patterns.reverse();
+    (cheapest[characters.len()].0, patterns)
+}
+
+/// Fewest guesses needed for part of a password, used to weigh repeats
+fn minimum_guesses(characters: &[char]) -> f64 {
+    cheapest_split(characters).0.exp2()
+}
+
+fn format_duration(seconds: f64) -> String {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = 60.0 * MINUTE;
+    const DAY: f64 = 24.0 * HOUR;
+    const MONTH: f64 = 31.0 * DAY;
+    const YEAR: f64 = 12.0 * MONTH;
+    const CENTURY: f64 = 100.0 * YEAR;
+
+    let (amount, unit) = match seconds {
+        seconds if seconds < 1.0 => return "less than a second".to_string(),
+        seconds if seconds < MINUTE => (seconds, "second"),
+        seconds if seconds < HOUR => (seconds / MINUTE, "minute"),
+        seconds if seconds < DAY => (seconds / HOUR, "hour"),
+        seconds if seconds < MONTH => (seconds / DAY, "day"),
+        seconds if seconds < YEAR => (seconds / MONTH, "month"),
+        seconds if seconds < CENTURY => (seconds / YEAR, "year"),
+        _ => return "centuries".to_string(),
+    };
+    let amount = amount.round() as u64;
+    if amount == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{amount} {unit}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_passwords() {
+        for password in ["password", "P@ssw0rd", "qwerty123", "aaaaaaaa"] {
+            let strength = Strength::estimate(password);
+            assert!(strength.is_weak(), "{password}: {strength}");
+        }
+        let strength = Strength::estimate("19/07/1994");
+        assert!(strength.weaknesses().contains(&Weakness::Date));
+        let strength = Strength::estimate("abcdefgh");
+        assert_eq!(strength.weaknesses(), [Weakness::Sequence]);
+        assert_eq!(Strength::estimate("").score(), 0);
+    }
+
+    #[test]
+    fn strong_passwords() {
+        for password in ["hT7#qL2!vB9@xZ4$", "Vx!3qp#Lr8$zN2wK"] {
+            let strength = Strength::estimate(password);
+            assert_eq!(strength.score(), 4, "{password}: {strength}");
+            assert!(strength.weaknesses().is_empty());
+        }
+    }
+
+    #[test]
+    fn words_lower_the_estimate() {
+        let words = Strength::estimate("gardenpiano");
+        let random = Strength::estimate("qmzvtkwhxnr");
+        assert!(words.weaknesses().contains(&Weakness::DictionaryWord));
+        assert!(words.entropy_bits() < random.entropy_bits());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0.5), "less than a second");
+        assert_eq!(format_duration(1.0), "1 second");
+        assert_eq!(format_duration(7200.0), "2 hours");
+        assert_eq!(format_duration(1e12), "centuries");
+    }
+}
@@ -0,0 +1,511 @@
+use super::super::builders::wordlist;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Most used passwords from public breach statistics, most common first
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Longest substring looked up in the dictionaries
+const MAX_WORD_LENGTH: usize = 20;
+
+/// Longest block looked for when detecting repeats
+const MAX_REPEAT_BLOCK: usize = 64;
+
+/// QWERTY rows, unshifted and shifted
+const KEYBOARD_ROWS: [(&str, &str); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+"),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|"),
+    ("asdfghjkl;'", "ASDFGHJKL:\""),
+    ("zxcvbnm,./", "ZXCVBNM<>?"),
+];
+
+/// Horizontal offset of every keyboard row, in keys
+const ROW_OFFSETS: [f64; 4] = [0.0, 1.5, 1.75, 2.25];
+
+/// Keys a keyboard walk can start from and their average number of
+/// neighbours
+const KEYBOARD_KEYS: f64 = 47.0;
+const KEYBOARD_DEGREE: f64 = 4.6;
+
+/// Days in a year, as every date guess has to try them all
+const DAYS_PER_YEAR: f64 = 365.0;
+
+/// Guessable pattern found in a password
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pattern {
+    CommonPassword,
+    DictionaryWord,
+    KeyboardWalk,
+    Repeat,
+    Sequence,
+    Date,
+}
+
+/// Part of a password that follows a pattern and how many guesses an
+/// attacker trying that pattern needs to find it
+#[derive(Clone, PartialEq, Debug)]
+pub struct Match {
+    pub start: usize,
+    /// Index after the last character of the match
+    pub end: usize,
+    pub pattern: Pattern,
+    pub guesses: f64,
+}
+
+impl Match {
+    fn new(start: usize, end: usize, pattern: Pattern, guesses: f64) -> Self {
+        Match {
+            start,
+            end,
+            pattern,
+            guesses: guesses.max(1.0),
+        }
+    }
+}
+
+/// Finds every pattern in the password, possibly overlapping
+pub fn find_matches(characters: &[char]) -> Vec<Match> {
+    let mut matches = dictionary_matches(characters);
+    matches.extend(keyboard_matches(characters));
+    matches.extend(repeat_matches(characters));
+    matches.extend(sequence_matches(characters));
+    matches.extend(date_matches(characters));
+    matches
+}
+
+/// Number of characters an attacker has to try for every position of a
+/// password using these characters
+pub fn cardinality(characters: &[char]) -> f64 {
+    let mut cardinality = 0.0;
+    if characters.iter().any(|c| c.is_ascii_lowercase()) {
+        cardinality += 26.0;
+    }
+    if characters.iter().any(|c| c.is_ascii_uppercase()) {
+        cardinality += 26.0;
+    }
+    if characters.iter().any(|c| c.is_ascii_digit()) {
+        cardinality += 10.0;
+    }
+    if characters
+        .iter()
+        .any(|c| c.is_ascii_punctuation() || *c == ' ')
+    {
+        cardinality += 33.0;
+    }
+    if characters.iter().any(|c| !c.is_ascii()) {
+        cardinality += 100.0;
+    }
+    cardinality
+}
+
+fn common_passwords() -> &'static HashMap<&'static str, usize> {
+    static RANKS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            .enumerate()
+            .map(|(rank, password)| (password, rank + 1))
+            .collect()
+    })
+}
+
+fn english_words() -> &'static HashMap<&'static str, usize> {
+    static WORDS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    WORDS.get_or_init(|| {
+        let words = wordlist();
+        words.iter().map(|word| (*word, words.len())).collect()
+    })
+}
+
+/// Undoes common character substitutions such as `4` for `a`, once with
+/// `1` read as `i` and once as `l`
+fn unleet(word: &[char]) -> [String; 2] {
+    let substitute = |character: char, one: char| match character {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '3' => 'e',
+        '9' => 'g',
+        '1' => one,
+        '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        other => other.to_ascii_lowercase(),
+    };
+    ['i', 'l'].map(|one| word.iter().map(|c| substitute(*c, one)).collect())
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k.min(n))
+        .fold(1.0, |result, i| result * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// Ways of mixing `changed` characters among `unchanged` ones, used for
+/// uppercase letters, shifted keys and substitutions
+fn variations(changed: usize, unchanged: usize) -> f64 {
+    if changed == 0 {
+        return 1.0;
+    }
+    if unchanged == 0 {
+        return 2.0;
+    }
+    (1..=changed.min(unchanged))
+        .map(|i| binomial(changed + unchanged, i))
+        .sum()
+}
+
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_ascii_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_ascii_lowercase()).count();
+    let capitalized =
+        word.first().is_some_and(|c| c.is_ascii_uppercase()) && upper == 1;
+    if capitalized {
+        2.0
+    } else {
+        variations(upper, lower)
+    }
+}
+
+fn dictionary_matches(characters: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for start in 0..characters.len() {
+        let longest = MAX_WORD_LENGTH.min(characters.len() - start);
+        for end in (start + 3)..=(start + longest) {
+            let word = &characters[start..end];
+            let lowercase: String =
+                word.iter().map(|c| c.to_ascii_lowercase()).collect();
+            let substitutions = word
+                .iter()
+                .zip(lowercase.chars())
+                .filter(|(c, _)| !c.is_ascii_alphabetic())
+                .count();
+
+            let mut candidates = vec![(lowercase.clone(), 1.0)];
+            for unleeted in unleet(word) {
+                if unleeted != lowercase {
+                    let letters = word.len() - substitutions;
+                    candidates
+                        .push((unleeted, variations(substitutions, letters)));
+                }
+            }
+
+            let best = candidates
+                .into_iter()
+                .filter_map(|(candidate, leet)| {
+                    if let Some(rank) = common_passwords().get(&*candidate) {
+                        Some((Pattern::CommonPassword, *rank as f64 * leet))
+                    } else {
+                        english_words().get(&*candidate).map(|rank| {
+                            (Pattern::DictionaryWord, *rank as f64 * leet)
+                        })
+                    }
+                })
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((pattern, guesses)) = best {
+                let guesses = guesses * uppercase_variations(word);
+                matches.push(Match::new(start, end, pattern, guesses));
+            }
+        }
+    }
+    matches
+}
+
+/// Row, horizontal position and whether shift is needed for a key
+fn key_position(character: char) -> Option<(usize, f64, bool)> {
+    KEYBOARD_ROWS
+        .iter()
+        .enumerate()
+        .find_map(|(row, (plain, shift))| {
+            let offset = ROW_OFFSETS[row];
+            if let Some(column) = plain.chars().position(|c| c == character) {
+                Some((row, offset + column as f64, false))
+            } else {
+                shift
+                    .chars()
+                    .position(|c| c == character)
+                    .map(|column| (row, offset + column as f64, true))
+            }
+        })
+}
+
+/// Direction from one key to a neighbouring one, if they are neighbours
+fn key_direction(from: char, to: char) -> Option<(i32, i32)> {
+    let (from_row, from_x, _) = key_position(from)?;
+    let (to_row, to_x, _) = key_position(to)?;
+    let rows = to_row as i32 - from_row as i32;
+    let columns = to_x - from_x;
+    let neighbours = match rows {
+        0 => columns.abs() == 1.0,
+        -1 | 1 => columns.abs() <= 1.0,
+        _ => false,
+    };
+    neighbours.then(|| (rows, columns.signum() as i32))
+}
+
+fn keyboard_guesses(walk: &[char], turns: usize) -> f64 {
+    let mut guesses = 0.0;
+    for length in 2..=walk.len() {
+        for turn in 1..=turns.min(length - 1) {
+            guesses += binomial(length - 1, turn - 1)
+                * KEYBOARD_KEYS
+                * KEYBOARD_DEGREE.powi(turn as i32);
+        }
+    }
+    let shifted = walk
+        .iter()
+        .filter(|c| key_position(**c).is_some_and(|(_, _, shift)| shift))
+        .count();
+    guesses * variations(shifted, walk.len() - shifted)
+}
+
+fn keyboard_matches(characters: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start < characters.len() {
+        let mut end = start + 1;
+        let mut turns = 0;
+        let mut direction = None;
+        while end < characters.len() {
+            match key_direction(characters[end - 1], characters[end]) {
+                Some(next) => {
+                    if direction != Some(next) {
+                        turns += 1;
+                        direction = Some(next);
+                    }
+                    end += 1;
+                }
+                None => break,
+            }
+        }
+        if end - start >= 3 {
+            let walk = &characters[start..end];
+            let guesses = keyboard_guesses(walk, turns);
+            matches.push(Match::new(
+                start,
+                end,
+                Pattern::KeyboardWalk,
+                guesses,
+            ));
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+fn repeat_matches(characters: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start < characters.len() {
+        let remaining = characters.len() - start;
+        let longest = (remaining / 2).min(MAX_REPEAT_BLOCK);
+        let best = (1..=longest)
+            .map(|block| {
+                let pattern = &characters[start..start + block];
+                let repeats = characters[start..]
+                    .chunks_exact(block)
+                    .take_while(|chunk| *chunk == pattern)
+                    .count();
+                (block, repeats)
+            })
+            .filter(|(block, repeats)| *repeats >= 2 && block * repeats >= 3)
+            .max_by_key(|(block, repeats)| block * repeats);
+
+        match best {
+            Some((block, repeats)) => {
+                let end = start + block * repeats;
+                let base = &characters[start..start + block];
+                let guesses = super::minimum_guesses(base) * repeats as f64;
+                matches.push(Match::new(start, end, Pattern::Repeat, guesses));
+                start = end;
+            }
+            None => start += 1,
+        }
+    }
+    matches
+}
+
+fn sequence_matches(characters: &[char]) -> Vec<Match> {
+    let same_class = |a: char, b: char| {
+        (a.is_ascii_lowercase() && b.is_ascii_lowercase())
+            || (a.is_ascii_uppercase() && b.is_ascii_uppercase())
+            || (a.is_ascii_digit() && b.is_ascii_digit())
+    };
+    let delta = |a: char, b: char| b as i64 - a as i64;
+
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start + 1 < characters.len() {
+        let step = delta(characters[start], characters[start + 1]);
+        let mut end = start + 1;
+        while end < characters.len()
+            && step.abs() == 1
+            && same_class(characters[start], characters[end])
+            && delta(characters[end - 1], characters[end]) == step
+        {
+            end += 1;
+        }
+        if end - start >= 3 {
+            let first = characters[start];
+            let starts = if "aAzZ019".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if step > 0 { 1.0 } else { 2.0 };
+            let guesses = starts * (end - start) as f64 * direction;
+            matches.push(Match::new(start, end, Pattern::Sequence, guesses));
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+fn current_year() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    1970 + (seconds / 31_556_952) as i64
+}
+
+/// Years between the date and now, with a minimum so that recent years
+/// are not considered trivial
+fn year_guesses(year: i64) -> f64 {
+    (year - current_year()).abs().max(20) as f64
+}
+
+/// Reads a year of two or four digits
+fn parse_year(digits: &str) -> Option<i64> {
+    let year: i64 = digits.parse().ok()?;
+    match digits.len() {
+        2 if year > 50 => Some(1900 + year),
+        2 => Some(2000 + year),
+        4 if (1000..=2050).contains(&year) => Some(year),
+        _ => None,
+    }
+}
+
+/// Year of a date given as three numbers in any usual order
+fn parse_date([first, second, third]: [&str; 3]) -> Option<i64> {
+    let is_day_and_month = |day: &str, month: &str| match (
+        day.parse::<u32>(),
+        month.parse::<u32>(),
+    ) {
+        (Ok(day), Ok(month)) => {
+            day.to_string().len() <= 2
+                && (1..=31).contains(&day)
+                && (1..=12).contains(&month)
+        }
+        _ => false,
+    };
+    let year_last =
+        is_day_and_month(first, second) || is_day_and_month(second, first);
+    let year_first = is_day_and_month(third, second);
+    year_last
+        .then(|| parse_year(third))
+        .flatten()
+        .or_else(|| year_first.then(|| parse_year(first)).flatten())
+}
+
+fn date_matches(characters: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for start in 0..characters.len() {
+        for end in (start + 4)..=(start + 10).min(characters.len()) {
+            let candidate: String = characters[start..end].iter().collect();
+            if let Some(guesses) = date_guesses(&candidate) {
+                matches.push(Match::new(start, end, Pattern::Date, guesses));
+            }
+        }
+    }
+    matches
+}
+
+fn date_guesses(candidate: &str) -> Option<f64> {
+    if candidate.chars().all(|c| c.is_ascii_digit()) {
+        if candidate.len() == 4 {
+            if let Some(year) = parse_year(candidate) {
+                return Some(year_guesses(year));
+            }
+        }
+        if candidate.len() > 8 {
+            return None;
+        }
+        // Every way of splitting the digits in three numbers
+        for first in 1..=4 {
+            for second in 1..=2 {
+                if first + second >= candidate.len() {
+                    continue;
+                }
+                let parts = [
+                    &candidate[..first],
+                    &candidate[first..first + second],
+                    &candidate[first + second..],
+                ];
+                if let Some(year) = parse_date(parts) {
+                    return Some(year_guesses(year) * DAYS_PER_YEAR);
+                }
+            }
+        }
+        return None;
+    }
+
+    let separator = candidate.chars().find(|c| !c.is_ascii_digit())?;
+    if !" -/._\\".contains(separator) {
+        return None;
+    }
+    let parts: Vec<&str> = candidate.split(separator).collect();
+    let parts: [&str; 3] = parts.try_into().ok()?;
+    let well_formed = parts.iter().all(|part| {
+        (1..=4).contains(&part.len())
+            && part.chars().all(|c| c.is_ascii_digit())
+    });
+    if !well_formed {
+        return None;
+    }
+    parse_date(parts).map(|year| year_guesses(year) * DAYS_PER_YEAR * 4.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(password: &str) -> Vec<Pattern> {
+        let characters: Vec<char> = password.chars().collect();
+        find_matches(&characters)
+            .into_iter()
+            .filter(|found| found.start == 0 && found.end == characters.len())
+            .map(|found| found.pattern)
+            .collect()
+    }
+
+    #[test]
+    fn finds_patterns() {
+        assert!(patterns("password").contains(&Pattern::CommonPassword));
+        assert!(patterns("P4ssw0rd").contains(&Pattern::CommonPassword));
+        assert!(patterns("Garden").contains(&Pattern::DictionaryWord));
+        assert!(patterns("qwerfdsa").contains(&Pattern::KeyboardWalk));
+        assert!(patterns("zxcvbn").contains(&Pattern::KeyboardWalk));
+        assert!(patterns("abcabcabc").contains(&Pattern::Repeat));
+        assert!(patterns("zzzz").contains(&Pattern::Repeat));
+        assert!(patterns("lmnopq").contains(&Pattern::Sequence));
+        assert!(patterns("98765").contains(&Pattern::Sequence));
+        assert!(patterns("1994").contains(&Pattern::Date));
+        assert!(patterns("19/07/1994").contains(&Pattern::Date));
+        assert!(patterns("1994-07-19").contains(&Pattern::Date));
+        assert!(patterns("190794").contains(&Pattern::Date));
+    }
+
+    #[test]
+    fn ignores_random_strings() {
+        assert!(patterns("x7#Kq").is_empty());
+        assert!(patterns("31/31/1994").is_empty());
+    }
+}
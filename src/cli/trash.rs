@@ -0,0 +1,86 @@
+use super::metadata::format_timestamp;
+use super::name::PasswordName;
+use serde::{Deserialize, Serialize};
+
+/// Password moved to the trash by `remove`, kept until it is restored or
+/// the trash is emptied
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TrashEntry {
+    /// Folder under the trash holding the versions, also naming the file
+    /// this entry is stored in
+    #[serde(skip)]
+    id: String,
+    name: String,
+    /// Seconds since the Unix epoch
+    deleted_at: u64,
+}
+
+impl TrashEntry {
+    pub(crate) fn new(
+        name: &PasswordName,
+        deleted_at: u64,
+        id: String,
+    ) -> Self {
+        TrashEntry {
+            id,
+            name: name.to_string(),
+            deleted_at,
+        }
+    }
+
+    /// Identifier of the entry, unique within the trash and sorting in
+    /// the order passwords were removed
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Sets the identifier, which is not serialized with the entry so that
+    /// storages can keep it as a key
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn deleted_at(&self) -> u64 {
+        self.deleted_at
+    }
+
+    /// Whether the password was deleted at least `age` seconds before `now`
+    pub fn is_older_than(&self, age: u64, now: u64) -> bool {
+        now.saturating_sub(self.deleted_at) >= age
+    }
+}
+
+impl std::fmt::Display for TrashEntry {
+    fn fmt(
+        &self,
+        formatter: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            formatter,
+            "{}  {}",
+            format_timestamp(self.deleted_at),
+            self.name
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::rotation::SECONDS_PER_DAY;
+
+    #[test]
+    fn entry_age() {
+        let name = PasswordName::new("bank").unwrap();
+        let entry = TrashEntry::new(&name, 10 * SECONDS_PER_DAY, "id".into());
+        let now = 40 * SECONDS_PER_DAY;
+        assert!(entry.is_older_than(30 * SECONDS_PER_DAY, now));
+        assert!(!entry.is_older_than(31 * SECONDS_PER_DAY, now));
+        assert_eq!(entry.to_string(), "1970-01-11 00:00:00 UTC  bank");
+    }
+}
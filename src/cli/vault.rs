@@ -0,0 +1,371 @@
+use super::atomic;
+use super::error::RepositoryError;
+use super::name::PasswordName;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Name of the file, inside the repository root, holding the vault header
+pub const HEADER_FILE: &str = ".vault";
+
+const FORMAT_VERSION: u32 = 2;
+const KDF_ALGORITHM: &str = "argon2id";
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 24;
+const ASSOCIATED_DATA: &[u8] = b"pwm-vault-v2";
+const VERIFIER_PLAINTEXT: &[u8] = b"pwm-master-password-check";
+
+/// Parameters of the Argon2id key derivation
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct KdfParams {
+    algorithm: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            algorithm: KDF_ALGORITHM.to_string(),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(
+        &self,
+        master_password: &str,
+        salt: &[u8],
+    ) -> Result<VaultKey, RepositoryError> {
+        if self.algorithm != KDF_ALGORITHM {
+            return Err(RepositoryError::Corrupt(format!(
+                "unsupported key derivation '{}'",
+                self.algorithm
+            )));
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )
+        .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+        argon2
+            .hash_password_into(master_password.as_bytes(), salt, &mut *key)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))?;
+
+        Ok(VaultKey {
+            cipher: XChaCha20Poly1305::new(key.as_ref().into()),
+        })
+    }
+}
+
+/// Header stored at the root of every vault. It holds everything needed to
+/// derive the encryption key from the master password, except the password
+/// itself.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct VaultHeader {
+    format: u32,
+    kdf: KdfParams,
+    /// Hex encoded salt of the key derivation
+    salt: String,
+    /// Hex encoded known plaintext, encrypted with the derived key, used to
+    /// tell a wrong master password apart from corrupt data
+    verifier: String,
+}
+
+impl VaultHeader {
+    pub fn create(
+        master_password: &str,
+    ) -> Result<(VaultHeader, VaultKey), RepositoryError> {
+        VaultHeader::create_with_params(master_password, KdfParams::default())
+    }
+
+    fn create_with_params(
+        master_password: &str,
+        kdf: KdfParams,
+    ) -> Result<(VaultHeader, VaultKey), RepositoryError> {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+
+        let key = kdf.derive_key(master_password, &salt)?;
+        let header = VaultHeader {
+            format: FORMAT_VERSION,
+            kdf,
+            salt: encode_hex(&salt),
+            verifier: encode_hex(
+                &key.encrypt(VERIFIER_PLAINTEXT, AssociatedData::Verifier),
+            ),
+        };
+        Ok((header, key))
+    }
+
+    pub fn unlock(
+        &self,
+        master_password: &str,
+    ) -> Result<VaultKey, RepositoryError> {
+        if self.format != FORMAT_VERSION {
+            return Err(RepositoryError::Corrupt(format!(
+                "unsupported vault format {}",
+                self.format
+            )));
+        }
+        let salt = decode_hex(&self.salt)?;
+        let verifier = decode_hex(&self.verifier)?;
+        let key = self.kdf.derive_key(master_password, &salt)?;
+
+        match key.decrypt(&verifier, AssociatedData::Verifier) {
+            Ok(plaintext) if plaintext == VERIFIER_PLAINTEXT => Ok(key),
+            _ => Err(RepositoryError::WrongMasterPassword),
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(
+        path: P,
+    ) -> Result<VaultHeader, RepositoryError> {
+        VaultHeader::from_json(&fs::read(path)?)
+    }
+
+    pub fn from_json(contents: &[u8]) -> Result<VaultHeader, RepositoryError> {
+        serde_json::from_slice(contents)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))
+    }
+
+    pub fn write<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<(), RepositoryError> {
+        atomic::write_file(path.as_ref(), &self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, RepositoryError> {
+        serde_json::to_vec_pretty(self)
+            .map_err(|error| RepositoryError::Corrupt(error.to_string()))
+    }
+}
+
+/// What an encrypted value holds and where it belongs, authenticated with
+/// it so that a value moved to another place of the store fails to decrypt
+/// instead of being read as something else
+#[derive(Clone, Copy, Debug)]
+pub enum AssociatedData<'a> {
+    /// Known plaintext of the header, checking the master password
+    Verifier,
+    /// Index of a vault file
+    Index,
+    Version {
+        name: &'a PasswordName,
+        version: u32,
+    },
+    Metadata {
+        name: &'a PasswordName,
+        version: u32,
+    },
+    Setting(&'a str),
+}
+
+impl AssociatedData<'_> {
+    fn to_bytes(self) -> Vec<u8> {
+        // Names and settings never contain NUL, which separates the fields
+        let fields = match self {
+            AssociatedData::Verifier => "verifier".to_string(),
+            AssociatedData::Index => "index".to_string(),
+            AssociatedData::Version { name, version } => {
+                format!("version\0{name}\0{version}")
+            }
+            AssociatedData::Metadata { name, version } => {
+                format!("metadata\0{name}\0{version}")
+            }
+            AssociatedData::Setting(setting) => format!("setting\0{setting}"),
+        };
+        [ASSOCIATED_DATA, b"\0", fields.as_bytes()].concat()
+    }
+}
+
+/// Key derived from the master password. Encrypted values are laid out as
+/// `nonce || ciphertext || tag` using XChaCha20-Poly1305. Keys can't be
+/// cloned, storages share the one of their repository through an `Rc` and
+/// the cipher wipes it once the last of them is dropped.
+pub struct VaultKey {
+    cipher: XChaCha20Poly1305,
+}
+
+impl VaultKey {
+    pub fn encrypt(&self, plaintext: &[u8], data: AssociatedData) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = data.to_bytes();
+        let payload = Payload {
+            msg: plaintext,
+            aad: &aad,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("Encryption with a valid key cannot fail");
+
+        let mut encrypted = nonce.to_vec();
+        encrypted.extend(ciphertext);
+        encrypted
+    }
+
+    pub fn decrypt(
+        &self,
+        encrypted: &[u8],
+        data: AssociatedData,
+    ) -> Result<Vec<u8>, RepositoryError> {
+        if encrypted.len() < NONCE_LENGTH {
+            return Err(RepositoryError::Corrupt(
+                "truncated ciphertext".into(),
+            ));
+        }
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        let aad = data.to_bytes();
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                RepositoryError::Corrupt("authentication failed".into())
+            })
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, RepositoryError> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(RepositoryError::Corrupt("invalid hex string".into()));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| {
+                RepositoryError::Corrupt("invalid hex string".into())
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zeroize::ZeroizeOnDrop;
+
+    const MASTER_PASSWORD: &str = "correct horse battery staple";
+
+    fn fast_params() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            ..KdfParams::default()
+        }
+    }
+
+    #[test]
+    fn keys_are_wiped_on_drop() {
+        fn wiped_on_drop<T: ZeroizeOnDrop>() {}
+        wiped_on_drop::<XChaCha20Poly1305>();
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let (_, key) =
+            VaultHeader::create_with_params(MASTER_PASSWORD, fast_params())
+                .unwrap();
+        let encrypted = key.encrypt(b"secret", AssociatedData::Index);
+        assert_ne!(&encrypted[NONCE_LENGTH..], b"secret");
+        assert_eq!(
+            key.decrypt(&encrypted, AssociatedData::Index).unwrap(),
+            b"secret"
+        );
+    }
+
+    #[test]
+    fn unlock_with_master_password() {
+        let (header, key) =
+            VaultHeader::create_with_params(MASTER_PASSWORD, fast_params())
+                .unwrap();
+        let encrypted = key.encrypt(b"secret", AssociatedData::Index);
+
+        let unlocked = header.unlock(MASTER_PASSWORD).unwrap();
+        assert_eq!(
+            unlocked.decrypt(&encrypted, AssociatedData::Index).unwrap(),
+            b"secret"
+        );
+        assert!(matches!(
+            header.unlock("wrong password"),
+            Err(RepositoryError::WrongMasterPassword)
+        ));
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let (_, key) =
+            VaultHeader::create_with_params(MASTER_PASSWORD, fast_params())
+                .unwrap();
+        let mut encrypted = key.encrypt(b"secret", AssociatedData::Index);
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(matches!(
+            key.decrypt(&encrypted, AssociatedData::Index),
+            Err(RepositoryError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn values_are_bound_to_their_place() {
+        let (_, key) =
+            VaultHeader::create_with_params(MASTER_PASSWORD, fast_params())
+                .unwrap();
+        let bank = PasswordName::new("bank").unwrap();
+        let mail = PasswordName::new("mail").unwrap();
+        let version =
+            |name, version| AssociatedData::Version { name, version };
+        let encrypted = key.encrypt(b"secret", version(&bank, 3));
+
+        assert!(key.decrypt(&encrypted, version(&bank, 3)).is_ok());
+        for moved in [
+            version(&mail, 3),
+            version(&bank, 1),
+            AssociatedData::Metadata {
+                name: &bank,
+                version: 3,
+            },
+            AssociatedData::Setting("rotation"),
+        ] {
+            assert!(matches!(
+                key.decrypt(&encrypted, moved),
+                Err(RepositoryError::Corrupt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join(HEADER_FILE);
+        let (header, _) =
+            VaultHeader::create_with_params(MASTER_PASSWORD, fast_params())
+                .unwrap();
+        header.write(&path).unwrap();
+        assert_eq!(VaultHeader::read(&path).unwrap(), header);
+    }
+}
@@ -1,7 +1,15 @@
 pub mod cli;
 
 use cli::CommandLineInterface;
-pub use cli::{Password, PasswordBuilder, PasswordRepository};
+pub use cli::{
+    AssociatedData, Breach, BreachList, Capitalization, CustomField,
+    DirectoryStorage, FieldChange, MemoryStorage, NoteLine, Otp, OtpAlgorithm,
+    OtpError, OtpKind, PassphraseOptions, Password, PasswordBuilder,
+    PasswordDiff, PasswordName, PasswordRepository, RepositoryError,
+    Retention, ReuseGroup, ReuseKind, ReuseReport, RotationPolicy, Storage,
+    StorageFormat, Strength, TrashEntry, VaultFileStorage, VaultHeader,
+    VaultKey, VersionMetadata, VersionReuse, Weakness,
+};
 
 pub fn main() {
     let mut command_line = CommandLineInterface::new();
@@ -1,37 +1,52 @@
 use assert_cmd::Command;
+use std::path::Path;
+
+const MASTER_PASSWORD: &str = "MASTER-PASSWORD";
+
+fn pwm(root_dir: &Path) -> Command {
+    let mut cmd = Command::cargo_bin("pwm").unwrap();
+    cmd.env("PASSWORDS_PATH", root_dir)
+        .env("PWM_MASTER_PASSWORD", MASTER_PASSWORD);
+    cmd
+}
 
 #[test]
 fn create_and_update_password() -> Result<(), Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("rm")
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+
+    pwm(root_dir.path())
+        .arg("rm")
         .arg("TO-DELETE")
         .write_stdin("YES\n")
         .assert()
-        .success();
+        .failure()
+        .code(2);
 
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("new")
+    pwm(root_dir.path())
+        .arg("new")
         .arg("TO-DELETE")
         .arg("OLD-PASSWORD-12345")
         .assert()
         .success();
 
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("update")
+    pwm(root_dir.path())
+        .arg("update")
         .arg("TO-DELETE")
         .arg("NEW-PASSWORD-12345")
         .assert()
         .success();
 
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("get")
+    pwm(root_dir.path())
+        .arg("get")
         .arg("TO-DELETE")
         .assert()
         .success()
         .stdout("TO-DELETE: NEW-PASSWORD-12345\n");
 
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("get")
+    pwm(root_dir.path())
+        .arg("get")
         .arg("TO-DELETE")
         .arg("--version")
         .arg("1")
@@ -39,8 +54,8 @@ fn create_and_update_password() -> Result<(), Box<dyn std::error::Error>> {
         .success()
         .stdout("TO-DELETE: OLD-PASSWORD-12345\n");
 
-    let mut cmd = Command::cargo_bin("pwm")?;
-    cmd.arg("rm")
+    pwm(root_dir.path())
+        .arg("rm")
         .arg("TO-DELETE")
         .write_stdin("YES")
         .assert()
@@ -48,3 +63,1268 @@ fn create_and_update_password() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn wrong_master_password() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .arg("new")
+        .arg("SECRET")
+        .arg("PASSWORD-12345")
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .env("PWM_MASTER_PASSWORD", "NOT-THE-MASTER-PASSWORD")
+        .arg("get")
+        .arg("SECRET")
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+#[test]
+fn exit_codes() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path())
+        .arg("get")
+        .arg("MISSING")
+        .assert()
+        .code(5);
+
+    pwm(root_dir.path())
+        .arg("init")
+        .env("PWM_MASTER_PASSWORD", "")
+        .assert()
+        .code(1);
+    assert!(!root_dir.path().join(".vault").exists());
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path()).arg("init").assert().code(6);
+
+    pwm(root_dir.path())
+        .arg("get")
+        .arg("MISSING")
+        .assert()
+        .code(2);
+    pwm(root_dir.path())
+        .arg("update")
+        .arg("MISSING")
+        .arg("PASSWORD-12345")
+        .assert()
+        .code(2);
+
+    pwm(root_dir.path())
+        .arg("new")
+        .arg("EXISTING")
+        .arg("PASSWORD-12345")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .arg("new")
+        .arg("EXISTING")
+        .arg("PASSWORD-12345")
+        .assert()
+        .code(4);
+    pwm(root_dir.path())
+        .arg("get")
+        .arg("EXISTING")
+        .arg("--version")
+        .arg("2")
+        .assert()
+        .code(3);
+    pwm(root_dir.path())
+        .env("PWM_MASTER_PASSWORD", "NOT-THE-MASTER-PASSWORD")
+        .arg("get")
+        .arg("EXISTING")
+        .assert()
+        .code(7);
+
+    Ok(())
+}
+
+#[test]
+fn plaintext_store() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+    // Layout written by versions without encryption
+    let password_folder = root_dir.path().join("bank");
+    std::fs::create_dir_all(&password_folder)?;
+    std::fs::write(password_folder.join("1"), "PASSWORD-12345")?;
+
+    let output = pwm(root_dir.path()).arg("init").output()?;
+    assert_eq!(output.status.code(), Some(6));
+    assert!(String::from_utf8(output.stderr)?
+        .contains("holds 1 unencrypted passwords"));
+    assert!(!root_dir.path().join(".vault").exists());
+    assert_eq!(
+        std::fs::read_to_string(password_folder.join("1"))?,
+        "PASSWORD-12345"
+    );
+
+    std::fs::remove_dir_all(&password_folder)?;
+    pwm(root_dir.path()).arg("init").assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn history() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .arg("new")
+        .arg("HISTORY")
+        .arg("OLD-PASSWORD-12345")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .arg("update")
+        .arg("HISTORY")
+        .arg("--message")
+        .arg("Rotated after leak")
+        .arg("NEW-PASSWORD-12345")
+        // As under cron, the author is looked up from the account
+        .env_remove("USER")
+        .env_remove("USERNAME")
+        .env_remove("LOGNAME")
+        .assert()
+        .success();
+
+    let output = pwm(root_dir.path())
+        .arg("history")
+        .arg("HISTORY")
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].trim_start().starts_with('2'));
+    assert!(lines[0].ends_with("Rotated after leak"));
+    #[cfg(unix)]
+    assert!(!lines[0].contains(" unknown@"));
+    assert!(lines[1].trim_start().starts_with('1'));
+    assert!(lines[1].contains(" UTC "));
+
+    Ok(())
+}
+
+#[test]
+fn structured_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "BANK", "PASSWORD-12345"])
+        .args(["--username", "alice"])
+        .args(["--url", "https://bank.example"])
+        .args(["--secret-field", "pin=1234"])
+        .args(["--field", "branch=Main street"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["get", "BANK"])
+        .assert()
+        .success()
+        .stdout(
+            "BANK: PASSWORD-12345\nusername: alice\nurl: https://bank.example\npin: ********\nbranch: Main street\n",
+        );
+    pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "pin"])
+        .assert()
+        .success()
+        .stdout("1234\n");
+
+    pwm(root_dir.path())
+        .args(["update", "BANK", "NEW-PASSWORD-12345"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "username"])
+        .assert()
+        .success()
+        .stdout("alice\n");
+    pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "missing"])
+        .assert()
+        .code(2);
+
+    // Editing a field keeps the value, generator flags replace it
+    pwm(root_dir.path())
+        .args([
+            "update",
+            "BANK",
+            "--notes",
+            "Call first",
+            "--username",
+            "bob",
+        ])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "value"])
+        .assert()
+        .success()
+        .stdout("NEW-PASSWORD-12345\n");
+    pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "username"])
+        .assert()
+        .success()
+        .stdout("bob\n");
+    pwm(root_dir.path())
+        .args(["update", "BANK", "--length", "20"])
+        .assert()
+        .success();
+    let output = pwm(root_dir.path())
+        .args(["get", "BANK", "--field", "value"])
+        .output()?;
+    assert_eq!(String::from_utf8(output.stdout)?.trim_end().len(), 20);
+
+    Ok(())
+}
+
+#[test]
+fn one_time_passwords() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+    let hotp = "otpauth://hotp/RFC4226?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=0";
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "HOTP", "PASSWORD-12345", "--otp", hotp])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["otp", "HOTP"])
+        .assert()
+        .success()
+        .stdout("755224\n");
+    pwm(root_dir.path())
+        .args(["otp", "HOTP"])
+        .assert()
+        .success()
+        .stdout("287082\n");
+    pwm(root_dir.path())
+        .args(["get", "HOTP", "--field", "otp"])
+        .assert()
+        .success()
+        .stdout(hotp.replace("counter=0", "counter=2") + "\n");
+
+    pwm(root_dir.path())
+        .args(["new", "TOTP", "PASSWORD-12345", "--otp", "JBSWY3DPEHPK3PXP"])
+        .assert()
+        .success();
+    let output = pwm(root_dir.path()).args(["otp", "TOTP"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let (code, seconds_left) = stdout.trim().split_once(' ').unwrap();
+    assert_eq!(code.len(), 6);
+    assert!(code.chars().all(|c| c.is_ascii_digit()));
+    assert!(seconds_left.ends_with("s left)"));
+
+    pwm(root_dir.path())
+        .args(["new", "INVALID", "PASSWORD-12345", "--otp", "not base32!"])
+        .assert()
+        .code(1);
+
+    Ok(())
+}
+
+#[test]
+fn passphrases() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    let output = pwm(root_dir.path())
+        .args(["gen", "--words", "4", "--separator", "_"])
+        .output()?;
+    assert!(output.status.success());
+    let passphrase = String::from_utf8(output.stdout)?;
+    assert_eq!(passphrase.trim().split('_').count(), 4);
+    assert_eq!(String::from_utf8(output.stderr)?, "Entropy: 51.7 bits\n");
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "PASSPHRASE", "--passphrase"])
+        .assert()
+        .success();
+    let output = pwm(root_dir.path())
+        .args(["get", "PASSPHRASE", "--field", "value"])
+        .output()?;
+    let passphrase = String::from_utf8(output.stdout)?;
+    assert_eq!(passphrase.trim().split('-').count(), 6);
+
+    for words in ["0", "65", "99999999999"] {
+        pwm(root_dir.path())
+            .args(["gen", "--words", words])
+            .assert()
+            .code(1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn character_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    for _ in 0..20 {
+        let output = pwm(root_dir.path())
+            .args(["gen", "--no-symbols", "--min-digits", "3"])
+            .args(["--exclude", "abc", "--avoid-ambiguous"])
+            .output()?;
+        assert!(output.status.success());
+        let password = String::from_utf8(output.stdout)?;
+        let password = password.trim();
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 3);
+        assert!(!password.contains(['a', 'b', 'c', '0', 'O', 'l', '1', 'I']));
+    }
+
+    pwm(root_dir.path())
+        .args(["gen", "--symbols", "!"])
+        .args(["--exclude", "!"])
+        .assert()
+        .code(1);
+
+    Ok(())
+}
+
+#[test]
+fn password_lengths() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    let output = pwm(root_dir.path())
+        .args(["gen", "--length", "1024"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?.trim_end().len(), 1024);
+
+    for _ in 0..20 {
+        let output = pwm(root_dir.path())
+            .args(["gen", "--min", "20", "--max", "24"])
+            .output()?;
+        let length = String::from_utf8(output.stdout)?.trim_end().len();
+        assert!((20..=24).contains(&length));
+    }
+
+    pwm(root_dir.path())
+        .args(["gen", "--min", "10", "--max", "9"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["gen", "--length", "0"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["gen", "--length", "many"])
+        .assert()
+        .code(1);
+
+    Ok(())
+}
+
+#[test]
+fn password_strength() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    let output = pwm(root_dir.path())
+        .args(["new", "WEAK", "qwerty123"])
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("weak password"));
+
+    let output = pwm(root_dir.path()).args(["check", "WEAK"]).output()?;
+    assert!(output.status.success());
+    let report = String::from_utf8(output.stdout)?;
+    assert!(report.starts_with("Score: 0/4"));
+    assert!(report.contains("Warning: it is one of the most used passwords"));
+
+    pwm(root_dir.path())
+        .args(["new", "STRICT", "password1", "--strict"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["get", "STRICT"])
+        .assert()
+        .code(2);
+    pwm(root_dir.path())
+        .args(["new", "STRICT", "hT7#qL2!vB9@xZ4$", "--strict"])
+        .assert()
+        .success()
+        .stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn breach_audit() -> Result<(), Box<dyn std::error::Error>> {
+    use sha1::{Digest, Sha1};
+
+    let root_dir = tempfile::tempdir()?;
+    let sha1_hex = |password: &str| -> String {
+        Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect()
+    };
+    let mut lines = [
+        format!("{}:42", sha1_hex("OLD-BREACHED-PASSWORD")),
+        format!("{}:7", sha1_hex("BREACHED-PASSWORD")),
+        format!("{}:1", sha1_hex("SOMETHING-ELSE")),
+    ];
+    lines.sort();
+    let breach_file = root_dir.path().join("pwned.txt");
+    std::fs::write(&breach_file, lines.join("\n") + "\n")?;
+    let vault_dir = root_dir.path().join("vault");
+
+    pwm(&vault_dir).arg("init").assert().success();
+    pwm(&vault_dir)
+        .args(["new", "BREACHED", "BREACHED-PASSWORD"])
+        .assert()
+        .success();
+    pwm(&vault_dir)
+        .args(["new", "ROTATED", "OLD-BREACHED-PASSWORD"])
+        .assert()
+        .success();
+    pwm(&vault_dir)
+        .args(["update", "ROTATED", "NEW-SAFE-PASSWORD"])
+        .assert()
+        .success();
+
+    pwm(&vault_dir)
+        .args(["audit", "--breaches"])
+        .arg(&breach_file)
+        .assert()
+        .success()
+        .stdout("BREACHED (version 1): seen 7 times in breaches\n");
+    pwm(&vault_dir)
+        .args(["audit", "--all-versions", "--breaches"])
+        .arg(&breach_file)
+        .assert()
+        .success()
+        .stdout(
+            "BREACHED (version 1): seen 7 times in breaches\nROTATED (version 1): seen 42 times in breaches\n",
+        );
+    pwm(&vault_dir)
+        .args(["audit", "--breaches"])
+        .arg(root_dir.path().join("missing.txt"))
+        .assert()
+        .code(9);
+
+    Ok(())
+}
+
+#[test]
+fn reuse_audit() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    for (name, value) in [
+        ("BANK", "Shared-Value-1"),
+        ("EMAIL", "Shared-Value-1"),
+        ("SHOP", "Shared-Value-2"),
+        ("VPN", "Rotated2023"),
+        ("UNIQUE", "x7#Kq2Lm9Vb4!pT8"),
+    ] {
+        pwm(root_dir.path())
+            .args(["new", name, value])
+            .assert()
+            .success();
+    }
+    pwm(root_dir.path())
+        .args(["update", "VPN", "Rotated2024"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["audit", "--reuse"])
+        .assert()
+        .success()
+        .stdout(
+            "identical values: BANK, EMAIL\nsimilar values: BANK, EMAIL, SHOP\nVPN version 2 is similar to version 1\n",
+        );
+
+    let output = pwm(root_dir.path())
+        .args(["audit", "--reuse", "--json"])
+        .output()?;
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["reuse"]["groups"][0]["kind"], "identical");
+    assert_eq!(report["reuse"]["groups"][0]["names"][1], "EMAIL");
+    assert_eq!(report["reuse"]["versions"][0]["name"], "VPN");
+    assert!(report.get("breaches").is_none());
+
+    pwm(root_dir.path()).arg("audit").assert().code(1);
+
+    Ok(())
+}
+
+#[test]
+fn rotation() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    let output = pwm(root_dir.path())
+        .args(["new", "PIN", "--length", "6"])
+        .args(["--no-lowercase", "--no-uppercase", "--no-symbols"])
+        .output()?;
+    assert!(output.status.success());
+    let pin = String::from_utf8(output.stdout)?;
+    let pin = pin.trim().strip_prefix("PIN: ").unwrap().to_string();
+    pwm(root_dir.path())
+        .args(["new", "WORK/VPN", "OLD-VPN-PASSWORD"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["policy", "--max-age", "90"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["policy", "WORK", "--max-age", "30"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .arg("policy")
+        .assert()
+        .success()
+        .stdout("*  90 days\nWORK/*  30 days\n");
+
+    pwm(root_dir.path())
+        .arg("stale")
+        .assert()
+        .success()
+        .stdout("");
+    std::fs::remove_file(root_dir.path().join("WORK/VPN/1.meta"))?;
+    pwm(root_dir.path())
+        .arg("stale")
+        .assert()
+        .success()
+        .stdout("WORK/VPN: unknown age, rotate every 30 days\n");
+
+    let output = pwm(root_dir.path()).args(["rotate", "PIN"]).output()?;
+    assert!(output.status.success());
+    let output = String::from_utf8(output.stdout)?;
+    let (old, new) = output.split_once('\n').unwrap();
+    assert_eq!(old, format!("Old: {pin}"));
+    let new = new.trim().strip_prefix("New: ").unwrap();
+    assert_eq!(new.len(), 6);
+    assert!(new.chars().all(|c| c.is_ascii_digit()));
+    let output = pwm(root_dir.path()).args(["history", "PIN"]).output()?;
+    assert!(String::from_utf8(output.stdout)?.contains("Rotated"));
+
+    Ok(())
+}
+
+#[test]
+fn hierarchical_names() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    for name in ["bank", "work/aws/prod", "work/aws", "work/vpn"] {
+        pwm(root_dir.path())
+            .args(["new", name, "A-PASSWORD-VALUE-123"])
+            .assert()
+            .success();
+    }
+    pwm(root_dir.path())
+        .args(["get", "work/aws", "--field", "password"])
+        .assert()
+        .success()
+        .stdout("A-PASSWORD-VALUE-123\n");
+    pwm(root_dir.path()).args(["get", "work"]).assert().code(2);
+
+    pwm(root_dir.path())
+        .arg("list")
+        .assert()
+        .success()
+        .stdout("bank\nwork/\n├── aws\n│   └── prod\n└── vpn\n");
+    pwm(root_dir.path())
+        .args(["list", "work/", "--flat"])
+        .assert()
+        .success()
+        .stdout("work/aws\nwork/aws/prod\nwork/vpn\n");
+    pwm(root_dir.path())
+        .args(["list", "work/aws", "--flat"])
+        .assert()
+        .success()
+        .stdout("work/aws\nwork/aws/prod\n");
+    pwm(root_dir.path())
+        .args(["list", "personal"])
+        .assert()
+        .code(2);
+
+    for name in ["../outside", "/etc/passwd", "work//aws", "work/./aws"] {
+        pwm(root_dir.path())
+            .args(["new", name, "A-PASSWORD-VALUE-123"])
+            .assert()
+            .code(1);
+    }
+    assert!(!root_dir.path().parent().unwrap().join("outside").exists());
+
+    pwm(root_dir.path())
+        .args(["rm", "work/aws"])
+        .write_stdin("yes\n")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["list", "--flat"])
+        .assert()
+        .success()
+        .stdout("bank\nwork/aws/prod\nwork/vpn\n");
+
+    Ok(())
+}
+
+#[test]
+fn path_traversal() -> Result<(), Box<dyn std::error::Error>> {
+    let parent = tempfile::tempdir()?;
+    let root_dir = parent.path().join("store");
+    let victim = parent.path().join("victim");
+    std::fs::create_dir_all(&victim)?;
+    std::fs::write(victim.join("1"), "NOT A PASSWORD")?;
+
+    pwm(&root_dir).arg("init").assert().success();
+    pwm(&root_dir)
+        .args(["new", "bank", "A-PASSWORD-VALUE-123"])
+        .assert()
+        .success();
+
+    let victim_path = victim.to_str().unwrap();
+    for name in [
+        "../victim",
+        "bank/../../victim",
+        victim_path,
+        "bank\\..\\..\\victim",
+        "bank\n",
+        "NUL",
+    ] {
+        for command in [
+            vec!["get", name],
+            vec!["new", name, "A-PASSWORD-VALUE-123"],
+            vec!["new", name],
+            vec!["update", name, "A-PASSWORD-VALUE-123"],
+            vec!["rm", name],
+            vec!["mv", name, "bank"],
+            vec!["cp", "bank", name],
+            vec!["history", name],
+            vec!["otp", name],
+            vec!["check", name],
+            vec!["rotate", name],
+            vec!["list", name],
+        ] {
+            let output = pwm(&root_dir)
+                .args(&command)
+                .write_stdin("yes\n")
+                .output()?;
+            assert_eq!(output.status.code(), Some(1), "{command:?}");
+            assert!(String::from_utf8(output.stderr)?
+                .contains("invalid password name"));
+        }
+    }
+    assert_eq!(std::fs::read(victim.join("1"))?, b"NOT A PASSWORD");
+    assert_eq!(std::fs::read_dir(&victim)?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn move_and_copy() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    for (name, value) in [
+        ("bank", "OLD-BANK-VALUE-123"),
+        ("email", "EMAIL-VALUE-12345"),
+    ] {
+        pwm(root_dir.path())
+            .args(["new", name, value])
+            .assert()
+            .success();
+    }
+    pwm(root_dir.path())
+        .args(["update", "bank", "NEW-BANK-VALUE-123", "--message", "Moved"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["mv", "missing", "other"])
+        .assert()
+        .code(2);
+    pwm(root_dir.path())
+        .args(["mv", "bank", "email"])
+        .assert()
+        .code(4);
+    pwm(root_dir.path())
+        .args(["cp", "bank", "email", "--overwrite"])
+        .assert()
+        .code(1);
+
+    pwm(root_dir.path())
+        .args(["mv", "bank", "finance/bank"])
+        .assert()
+        .success();
+    pwm(root_dir.path()).args(["get", "bank"]).assert().code(2);
+    pwm(root_dir.path())
+        .args([
+            "get",
+            "finance/bank",
+            "--version",
+            "1",
+            "--field",
+            "password",
+        ])
+        .assert()
+        .success()
+        .stdout("OLD-BANK-VALUE-123\n");
+    let output = pwm(root_dir.path())
+        .args(["history", "finance/bank"])
+        .output()?;
+    assert!(String::from_utf8(output.stdout)?.contains("Moved"));
+
+    pwm(root_dir.path())
+        .args(["cp", "finance/bank", "email", "--force"])
+        .assert()
+        .success();
+    for name in ["finance/bank", "email"] {
+        pwm(root_dir.path())
+            .args(["get", name, "--field", "password"])
+            .assert()
+            .success()
+            .stdout("NEW-BANK-VALUE-123\n");
+    }
+    pwm(root_dir.path())
+        .args(["get", "email", "--version", "3"])
+        .assert()
+        .code(3);
+    pwm(root_dir.path())
+        .args(["list", "--flat"])
+        .assert()
+        .success()
+        .stdout("email\nfinance/bank\n");
+
+    Ok(())
+}
+
+#[test]
+fn trash() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    for name in ["bank", "email"] {
+        pwm(root_dir.path())
+            .args(["new", name, "A-PASSWORD-VALUE-123"])
+            .assert()
+            .success();
+    }
+    pwm(root_dir.path())
+        .args(["update", "bank", "NEW-PASSWORD-VALUE-123"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["rm", "bank"])
+        .write_stdin("no\n")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["rm", "bank"])
+        .write_stdin("yes\n")
+        .assert()
+        .success();
+    pwm(root_dir.path()).args(["get", "bank"]).assert().code(2);
+    let output = pwm(root_dir.path()).args(["trash", "list"]).output()?;
+    let listed = String::from_utf8(output.stdout)?;
+    assert!(listed.ends_with(" UTC  bank\n"), "{listed}");
+
+    pwm(root_dir.path())
+        .args(["trash", "restore", "bank"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["get", "bank", "--version", "1", "--field", "password"])
+        .assert()
+        .success()
+        .stdout("A-PASSWORD-VALUE-123\n");
+    pwm(root_dir.path())
+        .args(["trash", "restore", "bank"])
+        .assert()
+        .code(2);
+
+    for name in ["bank", "email"] {
+        pwm(root_dir.path())
+            .args(["rm", name])
+            .write_stdin("yes\n")
+            .assert()
+            .success();
+    }
+    pwm(root_dir.path())
+        .args(["trash", "empty", "--older-than", "soon"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["trash", "empty", "--older-than", "30d"])
+        .assert()
+        .success();
+    let output = pwm(root_dir.path()).arg("trash").output()?;
+    assert_eq!(String::from_utf8(output.stdout)?.lines().count(), 2);
+    pwm(root_dir.path())
+        .args(["trash", "empty"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["trash", "list"])
+        .assert()
+        .success()
+        .stdout("");
+
+    pwm(root_dir.path())
+        .args(["new", "bank", "A-PASSWORD-VALUE-123"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["rm", "bank", "--permanent"])
+        .write_stdin("yes\n")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["trash", "restore", "bank"])
+        .assert()
+        .code(2);
+
+    Ok(())
+}
+
+#[test]
+fn rollback() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "bank", "OLD-PASSWORD-12345"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["update", "bank", "NEW-PASSWORD-12345"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["rollback", "bank", "latest"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["rollback", "bank", "7"])
+        .assert()
+        .code(3);
+    pwm(root_dir.path())
+        .args(["rollback", "missing", "1"])
+        .assert()
+        .code(2);
+    pwm(root_dir.path())
+        .args(["rollback", "bank", "1", "--message", "New one was rejected"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["get", "bank", "--field", "password"])
+        .assert()
+        .success()
+        .stdout("OLD-PASSWORD-12345\n");
+    pwm(root_dir.path())
+        .args(["get", "bank", "--version", "2", "--field", "password"])
+        .assert()
+        .success()
+        .stdout("NEW-PASSWORD-12345\n");
+    let output = pwm(root_dir.path()).args(["history", "bank"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].trim_start().starts_with('3'));
+    assert!(
+        lines[0].ends_with("(rollback to version 1)  New one was rejected")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn prune() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    for name in ["bank", "email"] {
+        pwm(root_dir.path())
+            .args(["new", name, "A-PASSWORD-VALUE-1"])
+            .assert()
+            .success();
+        for value in ["A-PASSWORD-VALUE-2", "A-PASSWORD-VALUE-3"] {
+            pwm(root_dir.path())
+                .args(["update", name, value])
+                .assert()
+                .success();
+        }
+    }
+
+    pwm(root_dir.path()).arg("prune").assert().code(1);
+    pwm(root_dir.path())
+        .args(["prune", "--keep-newer-than", "forever"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["prune", "--keep-last", "1", "--dry-run"])
+        .assert()
+        .success()
+        .stdout("bank: 1, 2\nemail: 1, 2\n");
+    pwm(root_dir.path())
+        .args(["get", "bank", "--version", "1"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["prune", "bank", "--keep-last", "2"])
+        .assert()
+        .success()
+        .stdout("bank: 1\n");
+    pwm(root_dir.path())
+        .args(["get", "bank", "--version", "1"])
+        .assert()
+        .code(3);
+    pwm(root_dir.path())
+        .args(["prune", "--keep-newer-than", "30d"])
+        .assert()
+        .success()
+        .stdout("");
+    pwm(root_dir.path())
+        .args(["prune", "--keep-last", "1"])
+        .assert()
+        .success()
+        .stdout("bank: 2\nemail: 1, 2\n");
+
+    pwm(root_dir.path())
+        .args(["update", "bank", "A-PASSWORD-VALUE-4"])
+        .assert()
+        .success();
+    let output = pwm(root_dir.path()).args(["history", "bank"]).output()?;
+    let versions: Vec<String> = String::from_utf8(output.stdout)?
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(String::from)
+        .collect();
+    assert_eq!(versions, ["4", "3"]);
+
+    Ok(())
+}
+
+#[test]
+fn diff() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "bank", "OLD-PASSWORD-12345", "--username", "alice"])
+        .args(["--notes", "Call first\nAsk for Bob"])
+        .assert()
+        .success();
+    pwm(root_dir.path()).args(["diff", "bank"]).assert().code(1);
+    pwm(root_dir.path())
+        .args(["update", "bank", "NEW-PASSWORD-12345"])
+        .args(["--secret-field", "pin=1234", "--notes", "Call first"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["update", "bank", "--username", "bob"])
+        .arg("NEW-PASSWORD-12345")
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["diff", "bank"])
+        .assert()
+        .success()
+        .stdout("--- bank version 2\n+++ bank version 3\n~ username: alice -> bob\n");
+    pwm(root_dir.path())
+        .args(["diff", "bank", "1", "2"])
+        .assert()
+        .success()
+        .stdout("--- bank version 1\n+++ bank version 2\n~ password: ******** -> ********\n+ pin: ********\nnotes:\n    Call first\n  - Ask for Bob\n");
+    let output = pwm(root_dir.path())
+        .args(["diff", "bank", "1", "--reveal"])
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.starts_with("--- bank version 1\n+++ bank version 3\n"));
+    assert!(stdout
+        .contains("~ password: OLD-PASSWORD-12345 -> NEW-PASSWORD-12345\n"));
+    assert!(stdout.contains("+ pin: 1234\n"));
+
+    pwm(root_dir.path())
+        .args(["diff", "bank", "2", "2"])
+        .assert()
+        .success()
+        .stdout("");
+    pwm(root_dir.path())
+        .args(["diff", "bank", "1", "9"])
+        .assert()
+        .code(3);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn private_store() -> Result<(), Box<dyn std::error::Error>> {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let root_dir = tempfile::tempdir()?;
+    let mode = |path: &Path| -> std::io::Result<u32> {
+        Ok(fs::metadata(path)?.permissions().mode() & 0o777)
+    };
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "work/bank", "PASSWORD-12345"])
+        .assert()
+        .success();
+    assert_eq!(mode(root_dir.path())?, 0o700);
+    assert_eq!(mode(&root_dir.path().join("work/bank"))?, 0o700);
+    assert_eq!(mode(&root_dir.path().join("work/bank/1"))?, 0o600);
+
+    fs::set_permissions(root_dir.path(), fs::Permissions::from_mode(0o755))?;
+    for args in [&["get", "work/bank"][..], &["list"], &["trash", "list"]] {
+        let output = pwm(root_dir.path()).args(args).output()?;
+        assert_eq!(output.status.code(), Some(10));
+        assert!(String::from_utf8(output.stderr)?
+            .contains("can be accessed by other users (mode 755)"));
+    }
+    pwm(root_dir.path()).arg("help").assert().success();
+
+    let output = pwm(root_dir.path())
+        .args(["--insecure-ok", "get", "work/bank"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "work/bank: PASSWORD-12345\n"
+    );
+    assert!(String::from_utf8(output.stderr)?
+        .starts_with("pwm: Warning: password store is exposed"));
+
+    fs::set_permissions(root_dir.path(), fs::Permissions::from_mode(0o700))?;
+    pwm(root_dir.path())
+        .args(["get", "work/bank"])
+        .assert()
+        .success();
+    Ok(())
+}
+
+#[test]
+fn concurrent_updates() -> Result<(), Box<dyn std::error::Error>> {
+    const UPDATES: usize = 12;
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "bank", "PASSWORD-12345"])
+        .assert()
+        .success();
+    std::thread::scope(|scope| {
+        for update in 0..UPDATES {
+            let root_dir = root_dir.path();
+            scope.spawn(move || {
+                pwm(root_dir)
+                    .args(["update", "bank", "--message"])
+                    .arg(format!("update {update}"))
+                    .arg(format!("PASSWORD-{update}-12345"))
+                    .assert()
+                    .success();
+            });
+        }
+    });
+
+    let output = pwm(root_dir.path()).args(["history", "bank"]).output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut versions = Vec::new();
+    let mut messages = Vec::new();
+    for line in stdout.lines() {
+        let version = line.split_whitespace().next().unwrap_or_default();
+        versions.push(version.parse::<usize>()?);
+        if let Some((_, message)) = line.split_once("update ") {
+            messages.push(message.parse::<usize>()?);
+        }
+    }
+    versions.sort_unstable();
+    messages.sort_unstable();
+    assert_eq!(versions, (1..=UPDATES + 1).collect::<Vec<_>>());
+    assert_eq!(messages, (0..UPDATES).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_hotp_codes() -> Result<(), Box<dyn std::error::Error>> {
+    const RUNS: usize = 12;
+    let root_dir = tempfile::tempdir()?;
+    let hotp = "otpauth://hotp/RFC4226?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=5";
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "HOTP", "PASSWORD-12345", "--otp", hotp])
+        .assert()
+        .success();
+    let mut codes: Vec<String> = std::thread::scope(|scope| {
+        let runs: Vec<_> = (0..RUNS)
+            .map(|_| {
+                let root_dir = root_dir.path();
+                scope.spawn(move || {
+                    let output = pwm(root_dir).args(["otp", "HOTP"]).output();
+                    String::from_utf8(output.unwrap().stdout).unwrap()
+                })
+            })
+            .collect();
+        runs.into_iter().map(|run| run.join().unwrap()).collect()
+    });
+    codes.sort_unstable();
+    codes.dedup();
+    assert_eq!(codes.len(), RUNS);
+
+    pwm(root_dir.path())
+        .args(["get", "HOTP", "--field", "otp"])
+        .assert()
+        .success()
+        .stdout(
+            hotp.replace("counter=5", &format!("counter={}", 5 + RUNS)) + "\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn busy_store() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    let lock = std::fs::File::create(root_dir.path().join(".lock"))?;
+    lock.lock()?;
+    let output = pwm(root_dir.path())
+        .env("PWM_LOCK_TIMEOUT", "0.1")
+        .arg("list")
+        .output()?;
+    assert_eq!(output.status.code(), Some(11));
+    assert!(String::from_utf8(output.stderr)?
+        .starts_with("pwm: The password store is busy"));
+    pwm(root_dir.path())
+        .env("PWM_LOCK_TIMEOUT", "soon")
+        .arg("list")
+        .assert()
+        .code(1);
+
+    lock.unlock()?;
+    pwm(root_dir.path()).arg("list").assert().success();
+    Ok(())
+}
+
+#[test]
+fn vault_format() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path())
+        .args(["init", "--format", "folders"])
+        .assert()
+        .code(1);
+    pwm(root_dir.path())
+        .args(["init", "--format", "vault"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["new", "work/bank", "PASSWORD-12345"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["update", "work/bank", "NEW-PASSWORD-12345"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["mv", "work/bank", "home/bank"])
+        .assert()
+        .success();
+
+    pwm(root_dir.path())
+        .args(["list", "--flat"])
+        .assert()
+        .success()
+        .stdout("home/bank\n");
+    pwm(root_dir.path())
+        .args(["get", "home/bank", "--version", "1"])
+        .assert()
+        .success()
+        .stdout("home/bank: PASSWORD-12345\n");
+    pwm(root_dir.path())
+        .args(["list"])
+        .env("PWM_MASTER_PASSWORD", "WRONG")
+        .assert()
+        .code(7);
+
+    // Names never reach the file system
+    let mut files: Vec<_> = std::fs::read_dir(root_dir.path())?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<Result<_, _>>()?;
+    files.sort();
+    assert_eq!(files, [".lock", "passwords.vault"]);
+    let vault = std::fs::read(root_dir.path().join("passwords.vault"))?;
+    assert!(!vault.windows(4).any(|window| window == b"bank"));
+
+    pwm(root_dir.path())
+        .args(["rm", "home/bank"])
+        .write_stdin("yes\n")
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout("");
+    pwm(root_dir.path())
+        .args(["trash", "restore", "home/bank"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["get", "home/bank"])
+        .assert()
+        .success()
+        .stdout("home/bank: NEW-PASSWORD-12345\n");
+    pwm(root_dir.path())
+        .args(["init", "--format", "vault"])
+        .assert()
+        .code(6);
+
+    Ok(())
+}
+
+#[test]
+fn vault_file_name() -> Result<(), Box<dyn std::error::Error>> {
+    let root_dir = tempfile::tempdir()?;
+
+    pwm(root_dir.path()).arg("init").assert().success();
+    pwm(root_dir.path())
+        .args(["new", "passwords.vault", "PASSWORD-12345"])
+        .assert()
+        .code(1);
+
+    // A folder with the name of the vault is never taken for one
+    std::fs::create_dir(root_dir.path().join("passwords.vault"))?;
+    pwm(root_dir.path())
+        .args(["new", "bank", "PASSWORD-12345"])
+        .assert()
+        .success();
+    pwm(root_dir.path())
+        .args(["get", "bank"])
+        .assert()
+        .success()
+        .stdout("bank: PASSWORD-12345\n");
+
+    Ok(())
+}